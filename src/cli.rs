@@ -1,5 +1,19 @@
-use clap::{Parser, Subcommand};
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
+use std::time::Duration;
+
+use crate::{
+    download::RetryPolicy,
+    export_command::ExportFormat,
+    inventory_command::InventoryFormat,
+    market::{Dataset, DatasetKind, KlineType, Market},
+    optimize_command::{Objective, ParameterRange},
+    resample::Interval,
+    strategy::{ExecutionPolicy, StrategyKind},
+    strategy_config::FeeConfig,
+    types::{DataSource, Granularity, StorageFormat},
+};
 
 #[derive(Debug, Parser)]
 #[command(about = "A fictional versioning CLI", long_about = None)]
@@ -23,16 +37,8 @@ pub struct Cli {
 pub enum Commands {
     #[command(arg_required_else_help = true)]
     Fetch {
-        /// The output directory to store the files
-        path: std::path::PathBuf,
-
-        /// The symbol name or Regex filter
-        #[arg(short, long, default_value_t = format!(".*"))]
-        symbol: String,
-
-        /// The interval
-        #[arg(short, long, default_value_t = format!("1m"))]
-        interval: String,
+        #[command(flatten)]
+        args: FetchArgs,
 
         /// Optional: start date (format: YYYY-MM-DD)
         #[arg(long)]
@@ -41,6 +47,22 @@ pub enum Commands {
         /// Optional: end date (format: YYYY-MM-DD)
         #[arg(long)]
         end_date: Option<String>,
+
+        /// Download daily archives, monthly archives or monthly archives where available
+        #[arg(long, value_enum, default_value_t = Granularity::Daily)]
+        granularity: Granularity,
+
+        /// Only download the missing and incomplete days between the first and last day on disk
+        /// (or the start and end date), without listing the archives
+        #[arg(long, default_value_t = false)]
+        repair: bool,
+    },
+
+    /// Fetches the days after the newest day fetched before (stored in `<path>/fetch-manifest.toml`)
+    #[command(arg_required_else_help = true)]
+    Sync {
+        #[command(flatten)]
+        args: FetchArgs,
     },
 
     #[command(arg_required_else_help = true)]
@@ -49,6 +71,34 @@ pub enum Commands {
         #[arg(short, long, default_value_t = format!(".*"))]
         symbol: String,
 
+        /// The interval of the klines to test on
+        #[arg(short, long, default_value = "1m")]
+        interval: Interval,
+
+        /// The strategy to test
+        #[arg(long, value_enum, default_value_t = StrategyKind::SmaRsi)]
+        strategy: StrategyKind,
+
+        /// Optional: strategy config file (.toml, .yaml or .yml)
+        #[arg(long)]
+        config: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// Optional: when orders are filled instead of the `execution` of the strategy config
+        #[arg(long, value_enum)]
+        execution: Option<ExecutionPolicy>,
+
+        /// Optional: write the result and the config used to this file (.toml, .yaml or .yml)
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Optional: replay the aggregated trades of this directory (e.g. `<path>/spot/aggTrades`)
+        /// to evaluate exits trade by trade
+        #[arg(long)]
+        agg_trades: Option<std::path::PathBuf>,
+
         /// Start date (format: YYYY-MM-DD)
         #[arg(long)]
         start_date: String,
@@ -67,6 +117,29 @@ pub enum Commands {
         #[arg(short, long, default_value_t = format!(".*"))]
         symbol: String,
 
+        /// The interval of the klines to test on
+        #[arg(short, long, default_value = "1m")]
+        interval: Interval,
+
+        /// The strategy to test
+        #[arg(long, value_enum, default_value_t = StrategyKind::SmaRsi)]
+        strategy: StrategyKind,
+
+        /// Optional: strategy config file (.toml, .yaml or .yml)
+        #[arg(long)]
+        config: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// Optional: when orders are filled instead of the `execution` of the strategy config
+        #[arg(long, value_enum)]
+        execution: Option<ExecutionPolicy>,
+
+        /// Optional: write the result and the config used to this file (.toml, .yaml or .yml)
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+
         /// Start date (format: YYYY-MM-DD)
         #[arg(long)]
         start_date: String,
@@ -79,6 +152,204 @@ pub enum Commands {
         path: std::path::PathBuf,
     },
 
+    #[command(arg_required_else_help = true)]
+    Optimize {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// The interval of the klines to test on
+        #[arg(short, long, default_value = "1m")]
+        interval: Interval,
+
+        /// The strategy to optimize
+        #[arg(long, value_enum, default_value_t = StrategyKind::SmaRsi)]
+        strategy: StrategyKind,
+
+        /// Optional: base strategy config file (.toml, .yaml or .yml)
+        #[arg(long)]
+        config: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// Optional: when orders are filled instead of the `execution` of the strategy config
+        #[arg(long, value_enum)]
+        execution: Option<ExecutionPolicy>,
+
+        /// Parameter range, e.g. `take-profit=2..10:1` or `rsi-threshold=60..90:5` (repeatable)
+        #[arg(short, long = "range", required = true)]
+        ranges: Vec<ParameterRange>,
+
+        /// The objective to rank the parameter combinations by
+        #[arg(long, value_enum, default_value_t = Objective::TotalReturn)]
+        objective: Objective,
+
+        /// Optional: write all results to this CSV file
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Start date (format: YYYY-MM-DD)
+        #[arg(long)]
+        start_date: String,
+
+        /// End date (format: YYYY-MM-DD)
+        #[arg(long)]
+        end_date: String,
+
+        /// The input directory to read the files from
+        path: std::path::PathBuf,
+    },
+
+    #[command(arg_required_else_help = true)]
+    WalkForward {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// The interval of the klines to test on
+        #[arg(short, long, default_value = "1m")]
+        interval: Interval,
+
+        /// The strategy to optimize
+        #[arg(long, value_enum, default_value_t = StrategyKind::SmaRsi)]
+        strategy: StrategyKind,
+
+        /// Optional: base strategy config file (.toml, .yaml or .yml)
+        #[arg(long)]
+        config: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        fees: FeeArgs,
+
+        /// Optional: when orders are filled instead of the `execution` of the strategy config
+        #[arg(long, value_enum)]
+        execution: Option<ExecutionPolicy>,
+
+        /// Parameter range, e.g. `take-profit=2..10:1` or `rsi-threshold=60..90:5` (repeatable)
+        #[arg(short, long = "range", required = true)]
+        ranges: Vec<ParameterRange>,
+
+        /// The objective to pick the parameters of each train window by
+        #[arg(long, value_enum, default_value_t = Objective::TotalReturn)]
+        objective: Objective,
+
+        /// Number of days to optimize the parameters on
+        #[arg(long, default_value_t = 90)]
+        train_days: i64,
+
+        /// Number of unseen days to test the optimized parameters on
+        #[arg(long, default_value_t = 30)]
+        test_days: i64,
+
+        /// Optional: write the result of each window to this CSV file
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Start date (format: YYYY-MM-DD)
+        #[arg(long)]
+        start_date: String,
+
+        /// End date (format: YYYY-MM-DD)
+        #[arg(long)]
+        end_date: String,
+
+        /// The input directory to read the files from
+        path: std::path::PathBuf,
+    },
+
+    #[command(arg_required_else_help = true)]
+    Compact {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// The interval
+        #[arg(short, long, default_value_t = format!("1m"))]
+        interval: String,
+
+        /// The data directory to read the CSV files from and to store the compact files in
+        path: std::path::PathBuf,
+    },
+
+    /// Resamples the daily CSV files into higher intervals, e.g. 1m into 5m, 1h and 1d
+    #[command(arg_required_else_help = true)]
+    Resample {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// The interval of the source files
+        #[arg(long, default_value = "1m")]
+        from: Interval,
+
+        /// The intervals to resample into (comma separated)
+        #[arg(long, value_delimiter = ',', required = true)]
+        to: Vec<Interval>,
+
+        /// The data directory to read the CSV files from and to store the resampled files in
+        path: std::path::PathBuf,
+    },
+
+    #[command(arg_required_else_help = true)]
+    Export {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// The interval
+        #[arg(short, long, default_value_t = format!("1m"))]
+        interval: String,
+
+        /// The format to export the klines to
+        #[arg(long, value_enum, default_value_t = ExportFormat::Parquet)]
+        format: ExportFormat,
+
+        /// Optional: the output directory (default: `<path>/parquet`)
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+
+        /// The data directory to read the CSV files from
+        path: std::path::PathBuf,
+    },
+
+    /// Reports the first and last day, missing days and incomplete days per symbol and interval
+    #[command(arg_required_else_help = true)]
+    Inventory {
+        /// The symbol name or Regex filter
+        #[arg(short, long, default_value_t = format!(".*"))]
+        symbol: String,
+
+        /// Optional: the interval (default: all intervals)
+        #[arg(short, long)]
+        interval: Option<String>,
+
+        /// The output format
+        #[arg(long, value_enum, default_value_t = InventoryFormat::Table)]
+        format: InventoryFormat,
+
+        /// The data directory to scan
+        path: std::path::PathBuf,
+    },
+
+    /// Moves the files of a data directory into the latest layout, `<market>/<interval>/<symbol>/YYYY/MM/`
+    #[command(arg_required_else_help = true)]
+    MigrateLayout {
+        /// The data directory to migrate
+        path: std::path::PathBuf,
+    },
+
+    /// Serves a local directory in the Binance public data layout, e.g. for offline fetches
+    #[command(arg_required_else_help = true)]
+    ServeMirror {
+        /// The address to listen on
+        #[arg(long, default_value_t = format!("127.0.0.1:8080"))]
+        address: String,
+
+        /// The directory containing `data/spot/...` and `data/futures/...`
+        path: std::path::PathBuf,
+    },
+
     #[command(arg_required_else_help = true)]
     Visualize {
         /// The symbol name or Regex filter
@@ -97,3 +368,116 @@ pub enum Commands {
         path: std::path::PathBuf,
     },
 }
+
+// What and where to fetch, shared by `fetch` and `sync`
+#[derive(Debug, Args)]
+pub struct FetchArgs {
+    /// The output directory to store the files
+    pub path: std::path::PathBuf,
+
+    /// The symbol name or Regex filter
+    #[arg(short, long, default_value_t = format!(".*"))]
+    pub symbol: String,
+
+    /// The interval
+    #[arg(short, long, default_value_t = format!("1m"))]
+    pub interval: String,
+
+    /// The file format(s) to store the klines in (Parquet files are stored in `<path>/parquet`)
+    #[arg(long, value_enum, default_value_t = StorageFormat::Csv)]
+    pub format: StorageFormat,
+
+    /// The market (futures klines are stored in `<path>/futures/<um|cm>`)
+    #[arg(long, value_enum, default_value_t = Market::Spot)]
+    pub market: Market,
+
+    /// Klines, aggregated trades or trades (trades are stored in `<path>/<market>/<dataset>`)
+    #[arg(long, value_enum, default_value_t = DatasetKind::Klines)]
+    pub dataset: DatasetKind,
+
+    /// Regular, mark price or index price klines (the latter only for futures)
+    #[arg(long, value_enum, default_value_t = KlineType::Klines)]
+    pub kline_type: KlineType,
+
+    /// How often failed downloads are retried
+    #[arg(long, default_value_t = 5)]
+    pub retries: u32,
+
+    /// The delay before the first retry in milliseconds, doubled with every further retry
+    #[arg(long, default_value_t = 1000)]
+    pub retry_backoff: u64,
+
+    /// The S3 bucket listing URL, e.g. of a `serve-mirror`
+    #[arg(
+        long,
+        default_value_t = format!("https://s3-ap-northeast-1.amazonaws.com/data.binance.vision")
+    )]
+    pub listing_url: String,
+
+    /// The URL to download the archives from
+    #[arg(long, default_value_t = format!("https://data.binance.vision"))]
+    pub download_url: String,
+}
+
+impl FetchArgs {
+    pub fn source(&self) -> DataSource {
+        DataSource {
+            listing_url: self.listing_url.clone(),
+            download_url: self.download_url.clone(),
+        }
+    }
+
+    pub fn dataset(&self) -> Result<Dataset> {
+        Dataset::new(self.market, self.dataset, self.kline_type)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: Duration::from_millis(self.retry_backoff),
+        }
+    }
+}
+
+// Overrides the fees of the strategy config (no doc comment, clap would use it as the about of
+// the commands)
+#[derive(Debug, Args)]
+pub struct FeeArgs {
+    /// Optional: use the fee schedule of this market
+    #[arg(long, value_enum)]
+    pub fee_market: Option<Market>,
+
+    /// Optional: use the fees of this VIP tier (0 to 9)
+    #[arg(long)]
+    pub vip_tier: Option<usize>,
+
+    /// Optional: maker fee in percent instead of the rate of the VIP tier (not discounted)
+    #[arg(long)]
+    pub maker_fee: Option<f64>,
+
+    /// Optional: taker fee in percent instead of the rate of the VIP tier (not discounted)
+    #[arg(long)]
+    pub taker_fee: Option<f64>,
+
+    /// Pay fees in BNB for a discount
+    #[arg(long, default_value_t = false)]
+    pub bnb_discount: bool,
+}
+
+impl FeeArgs {
+    pub fn apply(&self, config: &mut FeeConfig) {
+        if let Some(market) = self.fee_market {
+            config.market = Some(market);
+        }
+        if let Some(vip_tier) = self.vip_tier {
+            config.vip_tier = vip_tier;
+        }
+        if self.maker_fee.is_some() {
+            config.maker = self.maker_fee;
+        }
+        if self.taker_fee.is_some() {
+            config.taker = self.taker_fee;
+        }
+        config.bnb_discount |= self.bnb_discount;
+    }
+}
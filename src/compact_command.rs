@@ -0,0 +1,71 @@
+use crate::{
+    compact_store,
+    klines::{self, Kline},
+    layout::Layout,
+    progress,
+};
+use anyhow::{Ok, Result};
+use chrono::NaiveDate;
+use indicatif::MultiProgress;
+use log::info;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Converts the daily CSV files into one compact kline store per symbol and interval
+pub fn compact(
+    symbol_filter: &Regex,
+    interval: &str,
+    data_dir: &Path,
+    layout: Layout,
+    progress: &MultiProgress,
+) -> Result<()> {
+    let scan_progress = progress::progress_bar(progress, "Scanning kline files");
+    let mut files_by_symbol: HashMap<String, Vec<(NaiveDate, PathBuf)>> = HashMap::new();
+    for file in layout.kline_files(data_dir, symbol_filter, interval)? {
+        files_by_symbol
+            .entry(file.symbol)
+            .or_default()
+            .push((file.date, file.path));
+    }
+    scan_progress.finish_and_clear();
+
+    let total_symbols = files_by_symbol.len();
+    let compact_progress = progress::progress_bar(progress, "Compacting klines");
+    let compacted_symbols = AtomicUsize::new(0);
+
+    files_by_symbol
+        .into_par_iter()
+        .map(|(symbol, mut files)| {
+            files.sort();
+            let mut klines = vec![];
+            for (_, path) in &files {
+                klines.extend(klines::read_csv::<Kline>(path)?);
+            }
+            klines.sort_by_key(|kline| kline.open_time);
+            klines.dedup_by_key(|kline| kline.open_time);
+
+            compact_store::write(
+                &compact_store::compact_path(data_dir, &symbol, interval),
+                &klines,
+            )?;
+
+            let compacted_symbols = compacted_symbols.fetch_add(1, Ordering::Relaxed) + 1;
+            compact_progress.set_message(format!(
+                "Compacting klines ({compacted_symbols}/{total_symbols} symbols)"
+            ));
+            Ok(())
+        })
+        .collect::<Result<()>>()?;
+    compact_progress.finish_and_clear();
+
+    info!(
+        "Compacted {total_symbols} symbols into {}",
+        compact_store::compact_dir(data_dir).display()
+    );
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use crate::klines::{self, Kline};
+use anyhow::{bail, Result};
+use memmap2::Mmap;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+// File layout (little endian):
+// - header: magic (8 bytes), version (u32), column count (u32), row count (u64)
+// - columns: open_time (i64), open, high, low, close, volume, quote_asset_volume (f64),
+//   `row count` values each
+const MAGIC: &[u8; 8] = b"MK42KLNS";
+const VERSION: u32 = 2;
+const COLUMNS: usize = 7;
+const HEADER_SIZE: usize = 24;
+const VALUE_SIZE: usize = 8;
+
+pub fn compact_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("compact")
+}
+
+pub fn compact_path(data_dir: &Path, symbol: &str, interval: &str) -> PathBuf {
+    compact_dir(data_dir).join(format!("{symbol}-{interval}.bin"))
+}
+
+/// Writes the klines (sorted by open time) as columnar binary file
+pub fn write(path: &Path, klines: &[Kline]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // write to a temporary file first, so readers never see a partial store
+    let temp_path = path.with_extension("bin.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(COLUMNS as u32).to_le_bytes())?;
+    writer.write_all(&(klines.len() as u64).to_le_bytes())?;
+
+    // the days are looked up in milliseconds
+    for kline in klines {
+        writer.write_all(&klines::milliseconds(kline.open_time).to_le_bytes())?;
+    }
+    let columns: [fn(&Kline) -> f64; COLUMNS - 1] = [
+        |kline| kline.open,
+        |kline| kline.high,
+        |kline| kline.low,
+        |kline| kline.close,
+        |kline| kline.volume,
+        |kline| kline.quote_asset_volume,
+    ];
+    for column in columns {
+        for kline in klines {
+            writer.write_all(&column(kline).to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// A memory-mapped compact kline store of one symbol and interval
+pub struct CompactKlines {
+    mmap: Mmap,
+    rows: usize,
+}
+
+impl CompactKlines {
+    pub fn open(path: &Path) -> Result<CompactKlines> {
+        let file = File::open(path)?;
+        // SAFETY: the store is only ever replaced by renaming a new file over it, never modified in place
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_SIZE || &mmap[0..8] != MAGIC {
+            bail!("{path:?} is not a compact kline store");
+        }
+        let version = u32::from_le_bytes(mmap[8..12].try_into()?);
+        let columns = u32::from_le_bytes(mmap[12..16].try_into()?) as usize;
+        if version != VERSION || columns != COLUMNS {
+            bail!(
+                "{path:?} has an unsupported version {version} with {columns} columns, run `compact` again"
+            );
+        }
+        let rows = u64::from_le_bytes(mmap[16..24].try_into()?) as usize;
+        if mmap.len() != HEADER_SIZE + rows * COLUMNS * VALUE_SIZE {
+            bail!("{path:?} is truncated");
+        }
+
+        Ok(CompactKlines { mmap, rows })
+    }
+
+    fn value(&self, column: usize, row: usize) -> [u8; VALUE_SIZE] {
+        let offset = HEADER_SIZE + (column * self.rows + row) * VALUE_SIZE;
+        self.mmap[offset..offset + VALUE_SIZE].try_into().unwrap()
+    }
+
+    fn open_time(&self, row: usize) -> i64 {
+        i64::from_le_bytes(self.value(0, row))
+    }
+
+    pub fn get(&self, row: usize) -> Kline {
+        let float = |column| f64::from_le_bytes(self.value(column, row));
+        Kline {
+            open_time: self.open_time(row),
+            open: float(1),
+            high: float(2),
+            low: float(3),
+            close: float(4),
+            volume: float(5),
+            quote_asset_volume: float(6),
+        }
+    }
+
+    /// Rows with an open time in the given range (in milliseconds)
+    pub fn rows(&self, open_times: Range<i64>) -> Range<usize> {
+        let partition_point = |time: i64| {
+            let (mut low, mut high) = (0, self.rows);
+            while low < high {
+                let middle = (low + high) / 2;
+                if self.open_time(middle) < time {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+            low
+        };
+        partition_point(open_times.start)..partition_point(open_times.end)
+    }
+
+    pub fn klines(&self, open_times: Range<i64>) -> Vec<Kline> {
+        self.rows(open_times).map(|row| self.get(row)).collect()
+    }
+}
@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+enum Format {
+    Toml,
+    Yaml,
+}
+
+fn format(path: &Path) -> Result<Format> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => Ok(Format::Toml),
+        Some("yaml" | "yml") => Ok(Format::Yaml),
+        _ => bail!("Unsupported file format: {path:?} (expected .toml, .yaml or .yml)"),
+    }
+}
+
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = fs::read_to_string(path)?;
+    let value = match format(path)? {
+        Format::Toml => toml::from_str(&content)?,
+        Format::Yaml => serde_yaml::from_str(&content)?,
+    };
+    Ok(value)
+}
+
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let content = match format(path)? {
+        Format::Toml => toml::to_string_pretty(value)?,
+        Format::Yaml => serde_yaml::to_string(value)?,
+    };
+    fs::write(path, content)?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use reqwest::{blocking::Client, header::RANGE, StatusCode};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    path::Path,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The longest delay between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How failed requests are retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// The delay before the first retry, doubled with every further retry
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with up to 50% jitter, so that the workers don't retry in lockstep
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_BACKOFF);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos();
+        backoff + backoff.mul_f64(f64::from(nanos % 1000) / 2000.0)
+    }
+
+    /// Calls `request` until it succeeds or the retries are used up. Client errors like a missing
+    /// file are not retried, except for rate limits.
+    pub fn retry<T>(&self, url: &str, mut request: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match request() {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt <= self.retries && is_retryable(&err) => {
+                    let delay = self.delay(attempt);
+                    debug!("Attempt {attempt} of {url} failed: {err:#}, retrying in {delay:?}");
+                    thread::sleep(delay);
+                }
+                Err(err) => return Err(err.context(format!("Gave up after {attempt} attempts"))),
+            }
+        }
+    }
+}
+
+/// Transport errors, server errors and rate limits may go away on the next attempt
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match status(err) {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => true,
+    }
+}
+
+/// Whether the file (or its checksum) does not exist, e.g. a day Binance did not publish
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    status(err) == Some(StatusCode::NOT_FOUND)
+}
+
+fn status(err: &anyhow::Error) -> Option<StatusCode> {
+    err.downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+}
+
+/// Downloads the file and verifies it against the SHA-256 checksum Binance publishes next to it
+pub fn download_verified(url: &str, path: &Path, retry_policy: &RetryPolicy) -> Result<()> {
+    let client = Client::new();
+    let expected = retry_policy.retry(url, || fetch_checksum(&client, url))?;
+
+    retry_policy.retry(url, || {
+        download_resumable(&client, url, path)?;
+
+        let actual = sha256(path)?;
+        if actual != expected {
+            // start over, resuming a corrupt file won't help
+            fs::remove_file(path)?;
+            bail!("Checksum mismatch: expected {expected}, got {actual}");
+        }
+        Ok(())
+    })
+}
+
+/// Continues a partially downloaded file with a range request
+fn download_resumable(client: &Client, url: &str, path: &Path) -> Result<()> {
+    let offset = fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+
+    let mut response = request.send()?;
+    let mut file = match response.status() {
+        StatusCode::PARTIAL_CONTENT => OpenOptions::new().append(true).open(path)?,
+        // the file is complete already
+        StatusCode::RANGE_NOT_SATISFIABLE => return Ok(()),
+        _ => {
+            response.error_for_status_ref()?;
+            File::create(path)?
+        }
+    };
+    response.copy_to(&mut file)?;
+    Ok(())
+}
+
+/// Fetches a page of the bucket listing, retrying failed requests and unexpected responses
+pub fn fetch_listing<T: DeserializeOwned>(url: &str, retry_policy: &RetryPolicy) -> Result<T> {
+    let client = Client::new();
+    retry_policy.retry(url, || {
+        let mut body = String::new();
+        client
+            .get(url)
+            .send()?
+            .error_for_status()?
+            .read_to_string(&mut body)?;
+        serde_xml_rs::from_str(&body).map_err(|err| anyhow!("Invalid listing: {err}"))
+    })
+}
+
+/// The checksum file looks like `<sha256>  BTCUSDT-1m-2023-08-07.zip`
+fn fetch_checksum(client: &Client, url: &str) -> Result<String> {
+    let mut body = String::new();
+    client
+        .get(format!("{url}.CHECKSUM"))
+        .send()?
+        .error_for_status()?
+        .read_to_string(&mut body)?;
+
+    let Some(checksum) = body.split_whitespace().next() else {
+        bail!("Empty checksum file for {url}");
+    };
+    Ok(checksum.to_lowercase())
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
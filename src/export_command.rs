@@ -0,0 +1,62 @@
+use crate::{
+    klines::{self, KlineRecord},
+    layout::Layout,
+    parquet_store, progress,
+};
+use anyhow::{Ok, Result};
+use clap::ValueEnum;
+use indicatif::MultiProgress;
+use log::info;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Parquet,
+}
+
+/// Exports the daily CSV files of an existing data directory, skipping files that exist already
+pub fn export(
+    symbol_filter: &Regex,
+    interval: &str,
+    format: ExportFormat,
+    data_dir: &Path,
+    layout: Layout,
+    output_dir: &Path,
+    progress: &MultiProgress,
+) -> Result<()> {
+    let files = layout.kline_files(data_dir, symbol_filter, interval)?;
+    let total_files = files.len();
+    let export_progress = progress::progress_bar(progress, "Exporting klines");
+    let exported_files = AtomicUsize::new(0);
+
+    files
+        .into_par_iter()
+        .map(|file| {
+            match format {
+                ExportFormat::Parquet => {
+                    let path =
+                        parquet_store::parquet_path(output_dir, &file.symbol, interval, &file.date);
+                    if !path.is_file() {
+                        let records = klines::read_csv::<KlineRecord>(&file.path)?;
+                        parquet_store::write(&path, &records)?;
+                    }
+                }
+            }
+
+            let exported_files = exported_files.fetch_add(1, Ordering::Relaxed) + 1;
+            export_progress.set_message(format!(
+                "Exporting klines ({exported_files}/{total_files} files)"
+            ));
+            Ok(())
+        })
+        .collect::<Result<()>>()?;
+    export_progress.finish_and_clear();
+
+    info!("Exported {total_files} files to {}", output_dir.display());
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use crate::{market::Market, strategy_config::FeeConfig};
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// Maker and taker fees in percent of the regular Binance VIP tiers 0 to 9
+const SPOT_RATES: [(f64, f64); 10] = [
+    (0.1, 0.1),
+    (0.09, 0.1),
+    (0.08, 0.1),
+    (0.042, 0.06),
+    (0.042, 0.054),
+    (0.036, 0.048),
+    (0.03, 0.042),
+    (0.024, 0.036),
+    (0.018, 0.03),
+    (0.012, 0.024),
+];
+const FUTURES_RATES: [(f64, f64); 10] = [
+    (0.02, 0.05),
+    (0.016, 0.04),
+    (0.014, 0.035),
+    (0.012, 0.032),
+    (0.01, 0.03),
+    (0.008, 0.027),
+    (0.006, 0.025),
+    (0.004, 0.022),
+    (0.002, 0.02),
+    (0.0, 0.017),
+];
+
+/// Whether an order rested in the order book (limit orders) or took liquidity (market orders)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// The maker and taker fees in percent of the traded value
+#[derive(Debug, Clone, Copy)]
+pub struct FeeModel {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+impl FeeModel {
+    /// Looks up the rates of the market and VIP tier and applies the BNB discount (25% on spot,
+    /// 10% on USD-M futures, none on COIN-M futures). Rates that are set explicitly are the
+    /// effective rates, they are not discounted.
+    pub fn new(config: &FeeConfig) -> Result<FeeModel> {
+        let market = config.market.unwrap_or(Market::Spot);
+        let rates = match market {
+            Market::Spot => &SPOT_RATES,
+            Market::Um | Market::Cm => &FUTURES_RATES,
+        };
+        let Some((maker, taker)) = rates.get(config.vip_tier) else {
+            bail!("Unknown VIP tier {}, expected 0 to 9", config.vip_tier);
+        };
+        let discount = match (config.bnb_discount, market) {
+            (true, Market::Spot) => 0.75,
+            (true, Market::Um) => 0.9,
+            _ => 1.0,
+        };
+        Ok(FeeModel {
+            maker: config.maker.unwrap_or(maker * discount),
+            taker: config.taker.unwrap_or(taker * discount),
+        })
+    }
+
+    /// The fee in percent
+    pub fn rate(&self, liquidity: Liquidity) -> f64 {
+        match liquidity {
+            Liquidity::Maker => self.maker,
+            Liquidity::Taker => self.taker,
+        }
+    }
+}
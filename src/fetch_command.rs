@@ -1,9 +1,16 @@
 use crate::klines;
 use crate::progress;
-use crate::{symbols, types::KlineArchive};
-use anyhow::{Ok, Result};
-use chrono::NaiveDate;
-use clap_verbosity_flag::{InfoLevel, Verbosity};
+use crate::{
+    download::{self, RetryPolicy},
+    inventory,
+    layout::Layout,
+    manifest::FetchManifest,
+    market::{Dataset, DatasetKind},
+    symbols,
+    types::{Archive, DataSource, Granularity, StorageFormat},
+};
+use anyhow::{bail, Ok, Result};
+use chrono::{Duration, NaiveDate};
 use crossbeam::{
     channel::{self},
     scope,
@@ -13,57 +20,152 @@ use crossbeam::{
     thread::Scope,
 };
 use indicatif::MultiProgress;
-use indicatif_log_bridge::LogWrapper;
-use std::path::{Path, PathBuf};
+use log::{error, info, warn};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// How the archives to download are found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchMode {
+    /// List all archives of every symbol
+    List,
+    /// Only download the missing and incomplete days of the symbols on disk, without listing
+    Repair,
+    /// List the archives after the newest day in the fetch manifest
+    Sync,
+}
+
+/// The failed listings, downloads and extractions of a fetch
+#[derive(Debug, Default)]
+struct Failures {
+    messages: Vec<String>,
+    /// The symbols whose days may be incomplete, their days are not added to the manifest
+    symbols: BTreeSet<String>,
+}
+
+impl Failures {
+    fn add(&mut self, symbol: Option<&str>, message: String) {
+        self.symbols.extend(symbol.map(str::to_string));
+        self.messages.push(message);
+    }
+}
 
+#[allow(clippy::too_many_arguments)]
 pub fn fetch(
+    source: DataSource,
+    dataset: Dataset,
     interval: String,
     symbol: String,
     start_date: Option<NaiveDate>,
     end_date: Option<NaiveDate>,
     data_dir: PathBuf,
-    verbosity: Verbosity<InfoLevel>,
+    format: StorageFormat,
+    granularity: Granularity,
+    mode: FetchMode,
+    retry_policy: RetryPolicy,
+    progress: &MultiProgress,
 ) -> Result<()> {
-    let progress = MultiProgress::new();
-    let logger = env_logger::Builder::new()
-        .filter_level(verbosity.log_level_filter())
-        .build();
+    if format.parquet() && dataset.kind != DatasetKind::Klines {
+        bail!("Only klines can be stored as Parquet files");
+    }
 
-    LogWrapper::new(progress.clone(), logger)
-        .try_init()
-        .unwrap();
+    // keep spot and futures klines and trades apart
+    let layout = Layout::init(&data_dir)?;
+    let manifest_path = FetchManifest::path(&data_dir);
+    let data_dir = dataset.data_dir(&data_dir, layout);
+    let failures = Mutex::new(Failures::default());
+    let manifest = Mutex::new(FetchManifest::load(&manifest_path)?);
+    let last_days = Mutex::new(BTreeMap::new());
+    let sync_manifest = (mode == FetchMode::Sync).then(|| manifest.lock().unwrap().clone());
 
     let (symbol_sender, symbol_receiver) = channel::unbounded();
     let (kline_url_sender, kline_url_receiver) = channel::unbounded();
     let (kline_download_sender, kline_download_receiver) = channel::unbounded();
     let (kline_extract_sender, kline_extract_receiver) = channel::unbounded();
 
-    let _ = scope(|scope| -> Result<()> {
-        spawn_fetch_symbols(scope, symbol_sender, symbol, &progress);
-        spawn_fetch_kline_urls(
-            scope,
-            symbol_receiver,
-            kline_url_sender,
-            interval,
+    // the archives of the missing days are known without listing the bucket
+    let repair_urls = match mode {
+        FetchMode::Repair => Some(repair_urls(
+            &source,
+            &manifest.lock().unwrap(),
+            dataset,
+            &interval,
+            &symbol,
             start_date,
             end_date,
-            &progress,
-        );
+            &data_dir,
+            layout,
+            progress,
+        )?),
+        _ => None,
+    };
+
+    let _ = scope(|scope| -> Result<()> {
+        match repair_urls {
+            Some(urls) => {
+                info!("Repairing {} days", urls.len());
+                for url in urls {
+                    kline_url_sender.send(url).unwrap();
+                }
+                drop(kline_url_sender);
+            }
+            None => {
+                spawn_fetch_symbols(
+                    scope,
+                    &source,
+                    dataset,
+                    symbol_sender,
+                    symbol,
+                    retry_policy,
+                    &failures,
+                    progress,
+                );
+                spawn_fetch_kline_urls(
+                    scope,
+                    &source,
+                    dataset,
+                    symbol_receiver,
+                    kline_url_sender,
+                    interval,
+                    start_date,
+                    end_date,
+                    granularity,
+                    sync_manifest.as_ref(),
+                    retry_policy,
+                    &failures,
+                    progress,
+                );
+            }
+        }
         spawn_download_klines(
             scope,
             kline_url_receiver,
             kline_download_sender,
             &data_dir,
-            &progress,
+            layout,
+            dataset,
+            format,
+            mode == FetchMode::Repair,
+            retry_policy,
+            &failures,
+            &manifest,
+            progress,
         )?;
         spawn_extract_klines(
             scope,
             kline_download_receiver,
             kline_extract_sender,
-            &progress,
+            &failures,
+            &manifest,
+            &last_days,
+            progress,
         )?;
 
-        let stats_progress = progress::progress_bar(&progress, "0 kline archives extracted");
+        let stats_progress = progress::progress_bar(progress, "0 kline archives extracted");
         scope.spawn(move |_| {
             let mut count = 0;
             for _ in kline_extract_receiver {
@@ -76,20 +178,121 @@ pub fn fetch(
     })
     .unwrap();
 
+    // `sync` continues after the newest day, which must not skip days that failed before it
+    let failures = failures.into_inner().unwrap();
+    let mut manifest = manifest.into_inner().unwrap();
+    for ((symbol, interval), last_day) in last_days.into_inner().unwrap() {
+        if !failures.symbols.contains(&symbol) {
+            manifest.update(dataset, &symbol, &interval, last_day);
+        }
+    }
+    manifest.save(&manifest_path)?;
+    if !failures.messages.is_empty() {
+        for failure in &failures.messages {
+            error!("{failure}");
+        }
+        bail!(
+            "{} listings or kline archives failed",
+            failures.messages.len()
+        );
+    }
     Ok(())
 }
 
+/// The daily archives of the missing and incomplete days of the symbols on disk, except for days
+/// whose archives are known to be incomplete, too
+#[allow(clippy::too_many_arguments)]
+fn repair_urls(
+    source: &DataSource,
+    manifest: &FetchManifest,
+    dataset: Dataset,
+    interval: &str,
+    symbol: &str,
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    data_dir: &Path,
+    layout: Layout,
+    progress: &MultiProgress,
+) -> Result<Vec<String>> {
+    let symbol_filter = Regex::new(symbol)?;
+    let file_interval = dataset.file_interval(interval);
+    let series = inventory::scan(
+        data_dir,
+        layout,
+        &symbol_filter,
+        Some(file_interval),
+        progress,
+    )?;
+
+    let mut urls = vec![];
+    for series in series {
+        let first_day = start_date.unwrap_or(series.first_day);
+        let last_day = end_date.unwrap_or(series.last_day);
+        let in_range = |date: &NaiveDate| *date >= first_day && *date <= last_day;
+
+        // days before the first and after the last day on disk are missing, too
+        let mut days: Vec<NaiveDate> = first_day
+            .iter_days()
+            .take_while(|date| *date < series.first_day && in_range(date))
+            .chain(series.missing_days.into_iter().filter(in_range))
+            .chain(
+                series
+                    .last_day
+                    .iter_days()
+                    .skip(1)
+                    .take_while(|date| in_range(date)),
+            )
+            .collect();
+        days.extend(
+            series
+                .incomplete_days
+                .iter()
+                .map(|day| day.date)
+                .filter(|day| in_range(day))
+                .filter(|day| !manifest.is_short(dataset, &series.symbol, interval, *day)),
+        );
+
+        let prefix = dataset.symbol_prefix("daily", &series.symbol, interval);
+        let file_stem = dataset.file_stem(&series.symbol, interval);
+        urls.extend(days.iter().map(|day| {
+            source.archive(&format!(
+                "{prefix}{file_stem}-{}.zip",
+                day.format("%Y-%m-%d")
+            ))
+        }));
+    }
+    Ok(urls)
+}
+
 // BTCUSDT, 1INCHUPUSDT, ...     -> Fetching symbols (1 Worker)
 // e.g. https://s3-ap-northeast-1.amazonaws.com/data.binance.vision?delimiter=/&prefix=data/spot/daily/klines/&marker=
-fn spawn_fetch_symbols(
-    scope: &Scope<'_>,
+#[allow(clippy::too_many_arguments)]
+fn spawn_fetch_symbols<'env>(
+    scope: &Scope<'env>,
+    source: &DataSource,
+    dataset: Dataset,
     symbol_sender: Sender<String>,
     symbol_filter: String,
+    retry_policy: RetryPolicy,
+    failures: &'env Mutex<Failures>,
     main_progress: &MultiProgress,
 ) {
     let progress = progress::progress_bar(main_progress, "Fetching symbols");
+    let source = source.clone();
     scope.spawn(move |_| {
-        symbols::fetch(&symbol_sender, symbol_filter).unwrap();
+        if let Err(err) = symbols::fetch(
+            &source,
+            dataset,
+            &symbol_sender,
+            symbol_filter,
+            &retry_policy,
+        ) {
+            warn!("Failed to list the symbols: {err}");
+            failures
+                .lock()
+                .unwrap()
+                .add(None, format!("Listing of the symbols failed: {err}"));
+        }
         progress.finish_with_message("Fetching symbols: done");
         drop(symbol_sender);
     });
@@ -97,13 +300,20 @@ fn spawn_fetch_symbols(
 
 // Kline URLs -> Fetching kline meta data (50 Workers)
 // e.g. https://s3-ap-northeast-1.amazonaws.com/data.binance.vision?delimiter=/&prefix=data/spot/daily/klines/1INCHBTC/1m/
-fn spawn_fetch_kline_urls(
-    scope: &Scope<'_>,
+#[allow(clippy::too_many_arguments)]
+fn spawn_fetch_kline_urls<'env>(
+    scope: &Scope<'env>,
+    source: &DataSource,
+    dataset: Dataset,
     symbol_receiver: Receiver<String>,
     kline_url_sender: Sender<String>,
     interval: String,
     start_date: Option<NaiveDate>,
     end_date: Option<NaiveDate>,
+    granularity: Granularity,
+    sync_manifest: Option<&'env FetchManifest>,
+    retry_policy: RetryPolicy,
+    failures: &'env Mutex<Failures>,
     main_progress: &MultiProgress,
 ) {
     let progress = progress::progress_bar(main_progress, "Waiting for symbols...");
@@ -114,6 +324,7 @@ fn spawn_fetch_kline_urls(
         let symbol_receiver = symbol_receiver.clone();
         let progress = progress.clone();
         let interval = interval.clone();
+        let source = source.clone();
 
         let _ = scope.spawn(move |_| -> Result<()> {
             for symbol in &symbol_receiver {
@@ -122,7 +333,30 @@ fn spawn_fetch_kline_urls(
                     number_of_workers,
                     symbol_receiver.len()
                 ));
-                klines::fetch_urls(&symbol, &interval, &kline_url_sender, start_date, end_date)?;
+                // continue after the newest day fetched before
+                let start_date = match sync_manifest
+                    .and_then(|manifest| manifest.last_day(dataset, &symbol, &interval))
+                {
+                    Some(last_day) => start_date.max(Some(last_day + Duration::days(1))),
+                    None => start_date,
+                };
+                if let Err(err) = klines::fetch_urls(
+                    &source,
+                    dataset,
+                    &symbol,
+                    &interval,
+                    &kline_url_sender,
+                    start_date,
+                    end_date,
+                    granularity,
+                    &retry_policy,
+                ) {
+                    warn!("Failed to list the archives of {symbol}: {err:#}");
+                    failures.lock().unwrap().add(
+                        Some(&symbol),
+                        format!("Listing of {symbol} failed: {err:#}"),
+                    );
+                }
             }
             progress.finish_with_message("Fetching kline urls: done");
             drop(kline_url_sender);
@@ -134,11 +368,19 @@ fn spawn_fetch_kline_urls(
 
 // Kline download -> Fetching kline data (50 Workers)
 // https://data.binance.vision/data/spot/daily/klines/1INCHBTC/1m/1INCHBTC-1m-2020-12-25.zip
-fn spawn_download_klines(
-    scope: &Scope<'_>,
+#[allow(clippy::too_many_arguments)]
+fn spawn_download_klines<'env>(
+    scope: &Scope<'env>,
     kline_url_receiver: Receiver<String>,
-    kline_download_sender: Sender<KlineArchive>,
+    kline_download_sender: Sender<Archive>,
     data_dir: &Path,
+    layout: Layout,
+    dataset: Dataset,
+    format: StorageFormat,
+    repair: bool,
+    retry_policy: RetryPolicy,
+    failures: &'env Mutex<Failures>,
+    manifest: &'env Mutex<FetchManifest>,
     main_progress: &MultiProgress,
 ) -> Result<()> {
     let progress = progress::progress_bar(main_progress, "Waiting for kline urls...");
@@ -157,7 +399,34 @@ fn spawn_download_klines(
                     number_of_workers,
                     kline_url_receiver.len()
                 ));
-                klines::download_klines(&kline_url, &kline_download_sender, &data_dir)?;
+                let extracted = manifest.lock().unwrap().is_extracted(&kline_url);
+                if let Err(err) = klines::download_archive(
+                    &kline_url,
+                    &kline_download_sender,
+                    &data_dir,
+                    layout,
+                    dataset,
+                    format,
+                    repair,
+                    extracted,
+                    &retry_policy,
+                ) {
+                    // the days to repair are not listed, Binance may not have published them
+                    if repair && download::is_not_found(&err) {
+                        info!("{kline_url} is not published, skipping it");
+                        continue;
+                    }
+                    warn!("Failed to download {kline_url}: {err:#}");
+                    // archives are named `<symbol>-<interval>-<date>.zip`
+                    let symbol = kline_url
+                        .rsplit('/')
+                        .next()
+                        .and_then(|file_name| file_name.split('-').next());
+                    failures
+                        .lock()
+                        .unwrap()
+                        .add(symbol, format!("Download of {kline_url} failed: {err:#}"));
+                }
             }
             progress.finish_with_message("Downloading klines: done");
             // println!("Drop kline_download_sender");
@@ -170,10 +439,13 @@ fn spawn_download_klines(
 }
 
 // Kline extract -> Extract klines (x workers)
-fn spawn_extract_klines(
-    scope: &Scope<'_>,
-    kline_download_receiver: Receiver<KlineArchive>,
+fn spawn_extract_klines<'env>(
+    scope: &Scope<'env>,
+    kline_download_receiver: Receiver<Archive>,
     kline_extract_sender: Sender<()>,
+    failures: &'env Mutex<Failures>,
+    manifest: &'env Mutex<FetchManifest>,
+    last_days: &'env Mutex<BTreeMap<(String, String), NaiveDate>>,
     main_progress: &MultiProgress,
 ) -> Result<()> {
     let progress = progress::progress_bar(main_progress, "Waiting for kline archives...");
@@ -190,7 +462,35 @@ fn spawn_extract_klines(
                     number_of_workers,
                     kline_download_receiver.len()
                 ));
-                klines::extract_klines(download.temp_file_path, download.target_directory).unwrap();
+                match klines::extract_archive(&download) {
+                    Result::Ok(extracted) => {
+                        let (dataset, symbol, interval) =
+                            (download.dataset, &download.symbol, &download.interval);
+                        if let Some(last_day) = extracted.last_day {
+                            let mut last_days = last_days.lock().unwrap();
+                            let newest = last_days
+                                .entry((symbol.clone(), interval.clone()))
+                                .or_insert(last_day);
+                            *newest = (*newest).max(last_day);
+                        }
+                        let mut manifest = manifest.lock().unwrap();
+                        if download.date.is_none() {
+                            manifest.add_extracted(&download.url);
+                        }
+                        for day in extracted.short_days {
+                            manifest.add_short(dataset, symbol, interval, day);
+                        }
+                    }
+                    Err(err) => {
+                        let path = download.temp_file_path.display();
+                        warn!("Failed to extract {path}: {err}");
+                        failures.lock().unwrap().add(
+                            Some(&download.symbol),
+                            format!("Extraction of {path} failed: {err}"),
+                        );
+                        continue;
+                    }
+                }
                 kline_extract_sender.send(()).unwrap();
             }
             progress.finish_with_message("Extracting klines: done");
@@ -0,0 +1,119 @@
+use crate::{layout::Layout, progress, resample::Interval};
+use anyhow::{Ok, Result};
+use chrono::{Duration, NaiveDate};
+use indicatif::MultiProgress;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+/// The daily files of one symbol and interval
+#[derive(Debug, Serialize)]
+pub struct Series {
+    pub symbol: String,
+    pub interval: String,
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    pub days: usize,
+    /// Days between the first and the last day without a file
+    pub missing_days: Vec<NaiveDate>,
+    /// Days with fewer rows than a complete day of the interval
+    pub incomplete_days: Vec<IncompleteDay>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncompleteDay {
+    pub date: NaiveDate,
+    pub rows: usize,
+    pub expected_rows: usize,
+}
+
+/// The number of klines of a complete day, unknown for trades and intervals that don't divide a day
+pub fn expected_rows(interval: &str) -> Option<usize> {
+    let interval = interval.parse::<Interval>().ok()?;
+    let day = Duration::days(1).num_milliseconds();
+    (day % interval.milliseconds() == 0).then(|| (day / interval.milliseconds()) as usize)
+}
+
+/// Scans the daily CSV files of the matching symbols and intervals (all if none is given)
+pub fn scan(
+    data_dir: &Path,
+    layout: Layout,
+    symbol_filter: &Regex,
+    interval: Option<&str>,
+    progress: &MultiProgress,
+) -> Result<Vec<Series>> {
+    let intervals = match interval {
+        Some(interval) => vec![interval.to_string()],
+        None => layout.intervals(data_dir)?,
+    };
+
+    let scan_progress = progress::progress_bar(progress, "Scanning data directory");
+    let mut series = vec![];
+    for interval in intervals {
+        scan_progress.set_message(format!("Scanning data directory ({interval})"));
+        let expected_rows = expected_rows(&interval);
+
+        let mut files_by_symbol: BTreeMap<String, Vec<_>> = BTreeMap::new();
+        for file in layout.kline_files(data_dir, symbol_filter, &interval)? {
+            files_by_symbol
+                .entry(file.symbol.clone())
+                .or_default()
+                .push(file);
+        }
+
+        for (symbol, files) in files_by_symbol {
+            // the files are sorted by date
+            let dates: Vec<NaiveDate> = files.iter().map(|file| file.date).collect();
+            let incomplete_days = match expected_rows {
+                Some(expected_rows) => files
+                    .into_par_iter()
+                    .map(|file| Ok((file.date, count_rows(&file.path)?)))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .filter(|(_, rows)| *rows < expected_rows)
+                    .map(|(date, rows)| IncompleteDay {
+                        date,
+                        rows,
+                        expected_rows,
+                    })
+                    .collect(),
+                None => vec![],
+            };
+            let (first_day, last_day) = (dates[0], dates[dates.len() - 1]);
+            let missing_days = first_day
+                .iter_days()
+                .take_while(|date| *date <= last_day)
+                .filter(|date| dates.binary_search(date).is_err())
+                .collect();
+
+            series.push(Series {
+                symbol,
+                interval: interval.clone(),
+                first_day,
+                last_day,
+                days: dates.len(),
+                missing_days,
+                incomplete_days,
+            });
+        }
+    }
+    scan_progress.finish_and_clear();
+    Ok(series)
+}
+
+/// Counts the rows of a CSV file, without header rows
+pub fn count_rows(path: &Path) -> Result<usize> {
+    let mut rows = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        if line?.starts_with(|c: char| c.is_ascii_digit()) {
+            rows += 1;
+        }
+    }
+    Ok(rows)
+}
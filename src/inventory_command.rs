@@ -0,0 +1,93 @@
+use crate::{
+    inventory::{self, Series},
+    layout::Layout,
+};
+use anyhow::{Ok, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use colored::Colorize;
+use indicatif::MultiProgress;
+use regex::Regex;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InventoryFormat {
+    Table,
+    Json,
+}
+
+/// Prints the first and last day, the missing days and the incomplete days of every symbol and
+/// interval in the data directory
+pub fn inventory(
+    symbol_filter: &Regex,
+    interval: Option<&str>,
+    format: InventoryFormat,
+    data_dir: &Path,
+    layout: Layout,
+    progress: &MultiProgress,
+) -> Result<()> {
+    let series = inventory::scan(data_dir, layout, symbol_filter, interval, progress)?;
+    match format {
+        InventoryFormat::Table => print_table(&series),
+        InventoryFormat::Json => println!("{}", serde_json::to_string_pretty(&series)?),
+    }
+    Ok(())
+}
+
+fn print_table(series: &[Series]) {
+    println!(
+        "{:<16} {:<10} {:<10} {:<10} {:>6} {:>8} {:>10}",
+        "symbol", "interval", "first day", "last day", "days", "missing", "incomplete"
+    );
+    for series in series {
+        let missing = format!("{:>8}", series.missing_days.len());
+        let incomplete = format!("{:>10}", series.incomplete_days.len());
+        println!(
+            "{:<16} {:<10} {:<10} {:<10} {:>6} {} {}",
+            series.symbol,
+            series.interval,
+            series.first_day,
+            series.last_day,
+            series.days,
+            if series.missing_days.is_empty() {
+                missing.normal()
+            } else {
+                missing.red()
+            },
+            if series.incomplete_days.is_empty() {
+                incomplete.normal()
+            } else {
+                incomplete.yellow()
+            },
+        );
+        if !series.missing_days.is_empty() {
+            println!("  missing: {}", day_ranges(&series.missing_days).join(", "));
+        }
+        if !series.incomplete_days.is_empty() {
+            let incomplete_days: Vec<String> = series
+                .incomplete_days
+                .iter()
+                .map(|day| format!("{} ({}/{} rows)", day.date, day.rows, day.expected_rows))
+                .collect();
+            println!("  incomplete: {}", incomplete_days.join(", "));
+        }
+    }
+}
+
+/// Joins consecutive days, e.g. `2021-01-03..2021-01-05`
+fn day_ranges(days: &[NaiveDate]) -> Vec<String> {
+    let mut ranges: Vec<(NaiveDate, NaiveDate)> = vec![];
+    for day in days {
+        match ranges.last_mut() {
+            Some((_, end)) if end.succ_opt() == Some(*day) => *end = *day,
+            _ => ranges.push((*day, *day)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{start}..{end}"),
+        })
+        .collect()
+}
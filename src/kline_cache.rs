@@ -0,0 +1,234 @@
+use crate::{
+    compact_store::{self, CompactKlines},
+    klines::{self, Kline},
+    layout::Layout,
+    progress,
+    resample::{Interval, Resampler},
+    trades::{self, AggTradeRecord},
+};
+use anyhow::{bail, Ok, Result};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use indicatif::MultiProgress;
+use log::{debug, warn};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The klines of one day by symbol
+pub type DayKlines = HashMap<String, Arc<[Kline]>>;
+
+pub trait KlineSource: Sync {
+    /// Returns the klines of all (matching) symbols available on the given day
+    fn klines(&self, day: &NaiveDate) -> Result<DayKlines>;
+
+    /// The interval of the klines
+    fn interval(&self) -> Interval;
+
+    /// Returns the aggregated trades of the symbol on the given day, if they are replayed
+    fn agg_trades(&self, _day: &NaiveDate, _symbol: &str) -> Result<Option<Vec<AggTradeRecord>>> {
+        Ok(None)
+    }
+}
+
+/// Reads the klines of a data directory on every call, from the compact
+/// store if there is one for the symbol and from the daily CSV files otherwise
+pub struct DataKlineSource {
+    pub data_dir: PathBuf,
+    pub layout: Layout,
+    pub symbol_filter: Regex,
+    pub interval: Interval,
+    pub compact_klines: HashMap<String, CompactKlines>,
+    /// Optional: the directory and layout to replay aggTrades from, e.g. `data/spot/aggTrades`
+    pub agg_trades_dir: Option<(Layout, PathBuf)>,
+}
+
+impl DataKlineSource {
+    pub fn new(
+        data_dir: PathBuf,
+        symbol_filter: Regex,
+        interval: Interval,
+    ) -> Result<DataKlineSource> {
+        let (layout, data_dir) = Layout::resolve(&data_dir)?;
+        let compact_regex = Regex::new(&format!(
+            r"^(?P<symbol>\w+)-{}\.bin$",
+            regex::escape(&interval.to_string())
+        ))
+        .unwrap();
+        let mut compact_klines = HashMap::new();
+
+        let compact_dir = compact_store::compact_dir(&data_dir);
+        if compact_dir.is_dir() {
+            for file in fs::read_dir(compact_dir)? {
+                let file = file?;
+                let file_name = file.file_name();
+                let Some(matches) = compact_regex.captures(file_name.to_str().unwrap_or_default())
+                else {
+                    continue;
+                };
+                let symbol = matches.name("symbol").unwrap().as_str().to_string();
+                if !symbol_filter.is_match(&symbol) {
+                    continue;
+                }
+                // e.g. stores of an older version, the CSV files are still there
+                match CompactKlines::open(&file.path()) {
+                    Result::Ok(klines) => {
+                        compact_klines.insert(symbol, klines);
+                    }
+                    Err(err) => warn!("{err}, reading the CSV files of {symbol} instead"),
+                }
+            }
+            debug!("Using compact klines of {} symbols", compact_klines.len());
+        }
+
+        Ok(DataKlineSource {
+            data_dir,
+            layout,
+            symbol_filter,
+            interval,
+            compact_klines,
+            agg_trades_dir: None,
+        })
+    }
+
+    /// Replays the aggTrades of the directory, e.g. `data/spot/aggTrades` or a versioned `data`
+    pub fn set_agg_trades_dir(&mut self, dir: PathBuf) -> Result<()> {
+        self.agg_trades_dir = Some(Layout::resolve(&dir)?);
+        Ok(())
+    }
+}
+
+impl KlineSource for DataKlineSource {
+    fn klines(&self, day: &NaiveDate) -> Result<DayKlines> {
+        let day_start = day.and_time(NaiveTime::MIN).and_utc().timestamp_millis();
+        let day_end = day_start + Duration::days(1).num_milliseconds();
+        let mut day_klines: DayKlines = self
+            .compact_klines
+            .par_iter()
+            .filter_map(|(symbol, compact_klines)| {
+                let klines = compact_klines.klines(day_start..day_end);
+                (!klines.is_empty()).then(|| (symbol.clone(), klines.into()))
+            })
+            .collect();
+
+        let interval = self.interval.to_string();
+        let symbol_files: Vec<_> = self
+            .layout
+            .day_files(&self.data_dir, &self.symbol_filter, &interval, day)?
+            .into_iter()
+            .filter(|file| !day_klines.contains_key(&file.symbol))
+            .collect();
+
+        let csv_klines = symbol_files
+            .into_par_iter()
+            .map(|file| Ok((file.symbol, klines::read_csv::<Kline>(&file.path)?.into())))
+            .collect::<Result<DayKlines>>()?;
+        day_klines.extend(csv_klines);
+
+        // symbols without files of the interval are resampled from their 1m files
+        let minute = "1m".parse::<Interval>().unwrap();
+        if self.interval != minute {
+            let minute_files: Vec<_> = self
+                .layout
+                .day_files(&self.data_dir, &self.symbol_filter, "1m", day)?
+                .into_iter()
+                .filter(|file| !day_klines.contains_key(&file.symbol))
+                .collect();
+            if let Some(file) = minute_files.first() {
+                if !self.interval.is_resamplable_from(minute) {
+                    bail!(
+                        "No {interval} klines of {} on {day}, run `resample --to {interval}` first",
+                        file.symbol
+                    );
+                }
+            }
+            let resampled_klines = minute_files
+                .into_par_iter()
+                .map(|file| {
+                    let mut resampler = Resampler::new(self.interval);
+                    let mut bars: Vec<Kline> = klines::read_kline_records(&file.path)?
+                        .into_iter()
+                        .filter_map(|record| resampler.push(&record))
+                        .map(|bar| Kline::from(&bar))
+                        .collect();
+                    bars.extend(resampler.finish().map(|bar| Kline::from(&bar)));
+                    Ok((file.symbol, bars.into()))
+                })
+                .collect::<Result<DayKlines>>()?;
+            day_klines.extend(resampled_klines);
+        }
+        Ok(day_klines)
+    }
+
+    fn agg_trades(&self, day: &NaiveDate, symbol: &str) -> Result<Option<Vec<AggTradeRecord>>> {
+        let Some((layout, agg_trades_dir)) = &self.agg_trades_dir else {
+            return Ok(None);
+        };
+        let path = layout.csv_path(agg_trades_dir, symbol, "aggTrades", day);
+        if !path.is_file() {
+            debug!("No aggTrades of {symbol} on {day}");
+            return Ok(None);
+        }
+        Ok(Some(trades::read_agg_trades(&path)?))
+    }
+
+    fn interval(&self) -> Interval {
+        self.interval
+    }
+}
+
+/// Klines of a whole date range loaded into memory once, to be shared across test runs
+pub struct KlineCache {
+    pub days: HashMap<NaiveDate, DayKlines>,
+    pub interval: Interval,
+}
+
+impl KlineCache {
+    pub fn load(
+        source: &dyn KlineSource,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        progress: &MultiProgress,
+    ) -> Result<KlineCache> {
+        let total_days = end_date.signed_duration_since(*start_date).num_days() + 1;
+        let days: Vec<NaiveDate> = (0..total_days)
+            .map(|i| *start_date + Duration::days(i))
+            .collect();
+
+        let progress_bar = progress::progress_bar(progress, "Loading klines");
+        let loaded_days = AtomicUsize::new(0);
+        let days = days
+            .par_iter()
+            .map(|day| {
+                let klines = source.klines(day)?;
+                let loaded_days = loaded_days.fetch_add(1, Ordering::Relaxed) + 1;
+                progress_bar
+                    .set_message(format!("Loading klines ({loaded_days}/{total_days} days)"));
+                Ok((*day, klines))
+            })
+            .collect::<Result<HashMap<NaiveDate, DayKlines>>>()?;
+        progress_bar.finish_and_clear();
+
+        Ok(KlineCache {
+            days,
+            interval: source.interval(),
+        })
+    }
+}
+
+impl KlineSource for KlineCache {
+    fn klines(&self, day: &NaiveDate) -> Result<DayKlines> {
+        Ok(self.days.get(day).cloned().unwrap_or_default())
+    }
+
+    fn interval(&self) -> Interval {
+        self.interval
+    }
+}
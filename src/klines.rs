@@ -1,64 +1,135 @@
 use crate::{
     date::DateString,
-    types::{self, KlineArchive},
+    download::{self, RetryPolicy},
+    inventory,
+    layout::Layout,
+    market::{Dataset, DatasetKind},
+    parquet_store, trades,
+    types::{self, Archive, DataSource, Granularity, StorageFormat},
 };
 use anyhow::Result;
-use chrono::NaiveDate;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime};
 use crossbeam::channel::Sender;
-use csv::StringRecord;
+use csv::{Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder};
 use log::debug;
 use regex::Regex;
-use serde::Deserialize;
-use serde_xml_rs::from_str;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use std::{
     env,
-    fs::File,
-    io::Read,
+    fs::{self, File},
     path::{Path, PathBuf},
 };
 
+#[allow(clippy::too_many_arguments)]
 pub fn fetch_urls(
+    source: &DataSource,
+    dataset: Dataset,
     symbol: &str,
     interval: &str,
     kline_url_sender: &Sender<String>,
     start_date: Option<NaiveDate>,
     end_date: Option<NaiveDate>,
+    granularity: Granularity,
+    retry_policy: &RetryPolicy,
+) -> Result<()> {
+    // the first day that is not covered by a monthly archive
+    let mut daily_start_date = start_date;
+
+    if granularity != Granularity::Daily {
+        let prefix = dataset.symbol_prefix("monthly", symbol, interval);
+        list_archives(source, &prefix, String::new(), retry_policy, |path| {
+            let regex = archive_date_regex();
+            let Some(captures) = regex.captures(path) else {
+                panic!("{}", format!("Can't parse date: {path}"));
+            };
+            let month_start =
+                format!("{}-{}-01", &captures["year"], &captures["month"]).parse_date();
+            let month_end = last_day_of_month(&month_start);
+            if start_date.is_some_and(|start_date| month_end < start_date)
+                || end_date.is_some_and(|end_date| month_start > end_date)
+            {
+                // ignore months that are not within the provided start and end dates
+                return;
+            }
+
+            let next_day = month_end + Duration::days(1);
+            if daily_start_date.is_none_or(|date| date < next_day) {
+                daily_start_date = Some(next_day);
+            }
+            kline_url_sender.send(source.archive(path)).unwrap();
+        })?;
+    }
+
+    if granularity != Granularity::Monthly {
+        let prefix = dataset.symbol_prefix("daily", symbol, interval);
+
+        // skip listing the days before the start date or covered by monthly archives already
+        let marker = match daily_start_date {
+            Some(date) => format!(
+                "{prefix}{}-{}",
+                dataset.file_stem(symbol, interval),
+                (date - Duration::days(1)).format("%Y-%m-%d.zip")
+            ),
+            None => String::new(),
+        };
+
+        list_archives(source, &prefix, marker, retry_policy, |path| {
+            let regex = archive_date_regex();
+            let Some(captures) = regex.captures(path) else {
+                panic!("{}", format!("Can't parse date: {path}"));
+            };
+            let Some(day) = captures.name("day") else {
+                return;
+            };
+            let date = &format!(
+                "{}-{}-{}",
+                &captures["year"],
+                &captures["month"],
+                day.as_str()
+            )
+            .parse_date();
+            if daily_start_date.is_some_and(|start_date| date < &start_date)
+                || end_date.is_some_and(|end_date| date > &end_date)
+            {
+                // ignore urls that are not within the provided start and end dates
+                return;
+            }
+
+            let kline_url = source.archive(path);
+            kline_url_sender.send(kline_url).unwrap();
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Matches the date of daily and monthly archives
+fn archive_date_regex() -> Regex {
+    Regex::new(r"-(?P<year>\d{4})-(?P<month>\d{2})(-(?P<day>\d{2}))?\.zip$").unwrap()
+}
+
+/// Lists all zip archives with the given prefix, starting after the marker
+fn list_archives(
+    source: &DataSource,
+    prefix: &str,
+    marker: String,
+    retry_policy: &RetryPolicy,
+    mut on_archive: impl FnMut(&str),
 ) -> Result<()> {
     let mut is_fetching = true;
-    let mut next_marker: String = String::new();
+    let mut next_marker = marker;
 
     while is_fetching {
-        let url = format!("https://s3-ap-northeast-1.amazonaws.com/data.binance.vision?delimiter=/&prefix=data/spot/daily/klines/{symbol}/{interval}/&marker={next_marker}");
+        let url = source.listing(prefix, &next_marker);
 
         // TODO logger
         //println!("Fetching {}", url);
-        let mut res = reqwest::blocking::get(&url)?;
-        let mut body = String::new();
-        res.read_to_string(&mut body)?;
-
-        let doc: types::KlineResult = from_str(&body).unwrap();
+        let doc: types::KlineResult = download::fetch_listing(&url, retry_policy)?;
         if let Some(contents) = doc.contents {
             contents
                 .iter()
                 .filter(|content| content.key.ends_with(".zip"))
-                .for_each(|item| {
-                    let path = &item.key;
-                    let regex = Regex::new(r"(?P<date>\d{4}-\d{2}-\d{2})\.zip$").unwrap();
-                    let Some(captures) = regex.captures(path) else {
-                        panic!("{}", format!("Can't parse date: {url}"));
-                    };
-
-                    let date = &captures.name("date").unwrap().as_str().parse_date();
-                    if start_date.is_some_and(|start_date| date < &start_date)
-                        || end_date.is_some_and(|end_date| date > &end_date)
-                    {
-                        // ignore urls that are not within the provided start and end dates
-                        return;
-                    }
-
-                    let kline_url = format!("https://data.binance.vision/{path}");
-                    kline_url_sender.send(kline_url).unwrap();
-                });
+                .for_each(|item| on_archive(&item.key));
         }
 
         if let Some(marker) = doc.next_marker {
@@ -71,13 +142,44 @@ pub fn fetch_urls(
     Ok(())
 }
 
-pub fn download_klines(
+pub fn last_day_of_month(date: &NaiveDate) -> NaiveDate {
+    let first_of_month = date.with_day(1).unwrap();
+    first_of_month + Months::new(1) - Duration::days(1)
+}
+
+/// Returns true if the data of the day is stored in all requested formats
+fn is_stored(
+    data_dir: &Path,
+    layout: Layout,
+    symbol: &str,
+    interval: &str,
+    date: &NaiveDate,
+    format: StorageFormat,
+) -> bool {
+    (!format.csv() || layout.csv_path(data_dir, symbol, interval, date).is_file())
+        && (!format.parquet()
+            || parquet_store::parquet_path(&data_dir.join("parquet"), symbol, interval, date)
+                .is_file())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn download_archive(
     url: &str,
-    kline_download_sender: &Sender<KlineArchive>,
+    archive_sender: &Sender<Archive>,
     data_dir: &Path,
+    layout: Layout,
+    dataset: Dataset,
+    format: StorageFormat,
+    repair: bool,
+    extracted: bool,
+    retry_policy: &RetryPolicy,
 ) -> Result<()> {
-    // Example url: https://data.binance.vision/data/spot/daily/klines/AAVEBUSD/1m/AAVEBUSD-1m-2023-08-07.zip
-    let regex = Regex::new(r"/klines/(?P<symbol>\w+)/(?P<interval>\w+)/\w+-\w+-(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})\.zip").unwrap();
+    // Example urls:
+    // https://data.binance.vision/data/spot/daily/klines/AAVEBUSD/1m/AAVEBUSD-1m-2023-08-07.zip
+    // https://data.binance.vision/data/spot/monthly/klines/AAVEBUSD/1m/AAVEBUSD-1m-2023-08.zip
+    // https://data.binance.vision/data/futures/um/daily/markPriceKlines/BTCUSDT/1m/BTCUSDT-1m-2023-08-07.zip
+    // https://data.binance.vision/data/spot/daily/aggTrades/AAVEBUSD/AAVEBUSD-aggTrades-2023-08-07.zip
+    let regex = Regex::new(r"/(?P<symbol>\w+)-(?P<interval>\w+)-(?P<year>\d{4})-(?P<month>\d{2})(-(?P<day>\d{2}))?\.zip$").unwrap();
     let Some(captures) = regex.captures(url) else {
         panic!("{}", format!("Can't parse URL: {url}"));
     };
@@ -86,49 +188,225 @@ pub fn download_klines(
     let interval = &captures.name("interval").unwrap().as_str();
     let year = &captures.name("year").unwrap().as_str();
     let month = &captures.name("month").unwrap().as_str();
-    let day = &captures.name("day").unwrap().as_str();
 
-    let file_name = format!("{symbol}-{interval}-{year}-{month}-{day}");
-    let target_path = data_dir.join(format!("{year}/{month}/{day}/{file_name}.csv"));
+    // daily archives contain one day, monthly archives all days of the month
+    let (period, file_name, dates) = match captures.name("day") {
+        Some(day) => {
+            let day = day.as_str();
+            let date = format!("{year}-{month}-{day}").parse_date();
+            (
+                "daily",
+                format!("{symbol}-{interval}-{year}-{month}-{day}"),
+                vec![date],
+            )
+        }
+        None => {
+            let month_start = format!("{year}-{month}-01").parse_date();
+            let dates = month_start
+                .iter_days()
+                .take_while(|date| date <= &last_day_of_month(&month_start))
+                .collect();
+            (
+                "monthly",
+                format!("{symbol}-{interval}-{year}-{month}"),
+                dates,
+            )
+        }
+    };
 
-    // Skip files that have been downloaded already
-    if target_path.is_file() {
+    // Skip files that have been downloaded already, unless they are repaired. Monthly archives
+    // that were extracted before may not cover every day of the month.
+    let is_stored = |date: &NaiveDate| is_stored(data_dir, layout, symbol, interval, date, format);
+    if !repair && (dates.iter().all(is_stored) || (extracted && dates.iter().any(is_stored))) {
         //println!("Skipped {} because it already exists", url);
         return Ok(());
     }
 
-    let temp_file_path = env::temp_dir().join(format!("{file_name}.zip"));
-    let mut temp_file = File::create(&temp_file_path)?;
+    // archives of different markets and kline types share their file names
+    let temp_file_path = env::temp_dir().join(format!(
+        "{}{file_name}.zip",
+        dataset.prefix(period).replace('/', "-")
+    ));
+    download::download_verified(url, &temp_file_path, retry_policy)?;
 
-    reqwest::blocking::get(url)?
-        .copy_to(&mut temp_file)
-        .unwrap();
-
-    kline_download_sender
-        .send(KlineArchive {
-            target_directory: target_path.parent().unwrap().to_path_buf(),
+    archive_sender
+        .send(Archive {
+            url: url.to_string(),
             temp_file_path,
+            data_dir: data_dir.to_path_buf(),
+            layout,
+            dataset,
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            date: captures
+                .name("day")
+                .map(|day| format!("{year}-{month}-{}", day.as_str()).parse_date()),
+            format,
+            repair,
         })
         .unwrap();
 
     Ok(())
 }
 
-pub fn extract_klines(
-    temp_file_path: PathBuf,
-    target_directory: PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let zip_file = File::open(temp_file_path)?;
+/// The days extracted from an archive
+#[derive(Debug, Default)]
+pub struct Extracted {
+    pub last_day: Option<NaiveDate>,
+    /// Repaired days that were kept, as the archive has no more rows than the stored file
+    pub short_days: Vec<NaiveDate>,
+}
+
+/// Extracts a daily or monthly archive into one file per day.
+/// The rows are streamed into the file of their day, as archives of trades can be huge.
+pub fn extract_archive(archive: &Archive) -> Result<Extracted, Box<dyn std::error::Error>> {
+    let zip_file = File::open(&archive.temp_file_path)?;
     let mut zip = zip::ZipArchive::new(&zip_file)?;
-    zip.extract(&target_directory)?;
 
-    let names: Vec<&str> = zip.file_names().collect();
+    let time_column = time_column(archive.dataset.kind);
+    let mut day_writer: Option<DayWriter> = None;
+    let mut extracted = Extracted::default();
+    let mut days = 0;
+    for index in 0..zip.len() {
+        let entry = zip.by_index(index)?;
+        let mut reader = ReaderBuilder::new().has_headers(false).from_reader(entry);
+        for record in reader.records() {
+            let record = record?;
+            // skip header rows
+            if record
+                .get(0)
+                .and_then(|id| id.parse::<i64>().ok())
+                .is_none()
+            {
+                continue;
+            }
+            let date = match archive.date {
+                Some(date) => date,
+                None => {
+                    let Some(time) = record.get(time_column).and_then(|time| time.parse().ok())
+                    else {
+                        return Err(format!("Invalid time in {record:?}").into());
+                    };
+                    timestamp(time).date()
+                }
+            };
+
+            if day_writer.as_ref().is_none_or(|writer| writer.date != date) {
+                if let Some(writer) = day_writer.take() {
+                    if date < writer.date {
+                        return Err(format!(
+                            "The rows of {} are not sorted by time",
+                            archive.temp_file_path.display()
+                        )
+                        .into());
+                    }
+                    writer.finish(archive, &mut extracted)?;
+                }
+                day_writer = Some(DayWriter::new(archive, date)?);
+                days += 1;
+            }
+            day_writer.as_mut().unwrap().write(archive, &record)?;
+        }
+    }
+
+    extracted.last_day = day_writer.as_ref().map(|writer| writer.date);
+    if let Some(writer) = day_writer {
+        writer.finish(archive, &mut extracted)?;
+    }
+
     debug!(
-        "Extracted {:?} into {}",
-        names,
-        target_directory.to_str().unwrap()
+        "Extracted {days} days of {} from {}",
+        archive.symbol,
+        archive.temp_file_path.to_str().unwrap()
     );
-    Ok(())
+    fs::remove_file(&archive.temp_file_path)?;
+    Ok(extracted)
+}
+
+/// The column of the open time of klines and the time of trades
+fn time_column(kind: DatasetKind) -> usize {
+    let (headers, column) = match kind {
+        DatasetKind::Klines => (csv_headers(), "open_time"),
+        DatasetKind::AggTrades => (trades::agg_trade_headers(), "transact_time"),
+        DatasetKind::Trades => (trades::trade_headers(), "time"),
+    };
+    headers.iter().position(|name| name == column).unwrap()
+}
+
+/// Writes the rows of one day of an archive. The CSV file is written next to the stored one and
+/// replaces it when the day is finished.
+struct DayWriter {
+    date: NaiveDate,
+    csv: Option<(PathBuf, Writer<File>)>,
+    rows: usize,
+    /// Parquet files are written at once when the day is finished
+    records: Vec<KlineRecord>,
+}
+
+impl DayWriter {
+    fn new(archive: &Archive, date: NaiveDate) -> Result<DayWriter> {
+        let csv = match archive.format.csv() {
+            true => {
+                let (data_dir, symbol, interval) =
+                    (&archive.data_dir, &archive.symbol, &archive.interval);
+                let csv_path = archive.layout.csv_path(data_dir, symbol, interval, &date);
+                fs::create_dir_all(csv_path.parent().unwrap())?;
+                let writer = WriterBuilder::new()
+                    .has_headers(false)
+                    .from_path(csv_path.with_extension("csv.tmp"))?;
+                Some((csv_path, writer))
+            }
+            false => None,
+        };
+        Ok(DayWriter {
+            date,
+            csv,
+            rows: 0,
+            records: vec![],
+        })
+    }
+
+    fn write(&mut self, archive: &Archive, record: &StringRecord) -> Result<()> {
+        if let Some((_, csv)) = &mut self.csv {
+            csv.write_record(record)?;
+        }
+        if archive.format.parquet() {
+            self.records.push(record.deserialize(Some(&csv_headers()))?);
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(self, archive: &Archive, extracted: &mut Extracted) -> Result<()> {
+        if let Some((csv_path, mut csv)) = self.csv {
+            csv.flush()?;
+            let temp_path = csv_path.with_extension("csv.tmp");
+            // keep a stored file that has as many rows, the archive is incomplete, too
+            if archive.repair
+                && csv_path.is_file()
+                && inventory::count_rows(&csv_path)? >= self.rows
+            {
+                debug!(
+                    "Keeping {}, the archive has no more rows",
+                    csv_path.display()
+                );
+                fs::remove_file(temp_path)?;
+                extracted.short_days.push(self.date);
+                return Ok(());
+            }
+            fs::rename(temp_path, csv_path)?;
+        }
+        if archive.format.parquet() {
+            let parquet_path = parquet_store::parquet_path(
+                &archive.data_dir.join("parquet"),
+                &archive.symbol,
+                &archive.interval,
+                &self.date,
+            );
+            parquet_store::write(&parquet_path, &self.records)?;
+        }
+        Ok(())
+    }
 }
 
 pub fn csv_headers() -> StringRecord {
@@ -148,14 +426,121 @@ pub fn csv_headers() -> StringRecord {
     ])
 }
 
+/// A daily CSV file of a symbol
+pub struct KlineFile {
+    pub symbol: String,
+    pub date: NaiveDate,
+    pub path: PathBuf,
+}
+
+/// Reads a kline CSV file as `Kline` or `KlineRecord`
+pub fn read_csv<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let mut reader = Reader::from_path(path)?;
+    reader.set_headers(csv_headers());
+    let klines = reader.deserialize().collect::<Result<Vec<T>, _>>()?;
+    Ok(klines)
+}
+
+/// Reads all rows of a kline CSV file, skipping a header row if there is one
+pub fn read_kline_records(path: &Path) -> Result<Vec<KlineRecord>> {
+    let mut reader = ReaderBuilder::new().has_headers(false).from_path(path)?;
+    let headers = csv_headers();
+    let mut records = vec![];
+    for record in reader.records() {
+        let record = record?;
+        if record
+            .get(0)
+            .and_then(|time| time.parse::<i64>().ok())
+            .is_none()
+        {
+            continue;
+        }
+        records.push(trades::deserialize(&record, &headers)?);
+    }
+    Ok(records)
+}
+
+/// Writes klines in the format of the Binance CSV files (without header)
+pub fn write_kline_records(path: &Path, records: &[KlineRecord]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = WriterBuilder::new().has_headers(false).from_path(path)?;
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A kline with all columns of the CSV files
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KlineRecord {
+    pub open_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub close_time: i64,
+    pub quote_asset_volume: f64,
+    pub number_of_trades: i64,
+    pub taker_buy_base_asset_volume: f64,
+    pub taker_buy_quote_asset_volume: f64,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Kline {
+    /// In milliseconds, also for files in microseconds
+    #[serde(deserialize_with = "deserialize_milliseconds")]
     pub open_time: i64,
     pub open: f64,
     pub high: f64,
     pub low: f64,
     pub close: f64,
     pub volume: f64,
+    pub quote_asset_volume: f64,
+}
+
+impl From<&KlineRecord> for Kline {
+    fn from(record: &KlineRecord) -> Kline {
+        Kline {
+            open_time: milliseconds(record.open_time),
+            open: record.open,
+            high: record.high,
+            low: record.low,
+            close: record.close,
+            volume: record.volume,
+            quote_asset_volume: record.quote_asset_volume,
+        }
+    }
+}
+
+/// Converts a timestamp in microseconds to milliseconds. Binance uses microseconds in the spot
+/// archives since 2025-01-01 and milliseconds before.
+pub fn milliseconds(time: i64) -> i64 {
+    if time > 100_000_000_000_000 {
+        time / 1000
+    } else {
+        time
+    }
+}
+
+fn deserialize_milliseconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Ok(milliseconds(i64::deserialize(deserializer)?))
+}
+
+/// Converts a timestamp in milliseconds or microseconds
+pub fn timestamp(time: i64) -> NaiveDateTime {
+    DateTime::from_timestamp_millis(milliseconds(time))
+        .expect("Invalid timestamp")
+        .naive_utc()
+}
+
+impl Kline {
+    pub fn open_timestamp(&self) -> NaiveDateTime {
+        timestamp(self.open_time)
+    }
 }
 
 impl ta::Close for Kline {
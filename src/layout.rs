@@ -0,0 +1,318 @@
+use crate::{
+    date::DateString,
+    klines::KlineFile,
+    market::{Dataset, DatasetKind, KlineType, Market},
+    resample::Interval,
+};
+use anyhow::{bail, Ok, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use log::warn;
+use regex::Regex;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Marks the root of a versioned data directory and contains the layout version
+const LAYOUT_FILE: &str = ".layout";
+
+/// The on-disk layout of the daily CSV files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// `YYYY/MM/DD/<symbol>-<interval>-<date>.csv`, all symbols and intervals of a day in one
+    /// directory, spot klines in the data directory itself and everything else in e.g.
+    /// `futures/um/klines` or `spot/aggTrades`
+    V1,
+    /// `<market>/<interval>/<symbol>/YYYY/MM/<symbol>-<interval>-<date>.csv`, where the market is
+    /// e.g. `spot` or `futures/um` and the interval is the dataset name for trades
+    V2,
+}
+
+impl Layout {
+    pub const LATEST: Layout = Layout::V2;
+
+    fn version(&self) -> u32 {
+        match self {
+            Layout::V1 => 1,
+            Layout::V2 => 2,
+        }
+    }
+
+    /// Reads the layout of the data directory or of the data directory containing it,
+    /// unversioned directories use the original layout
+    pub fn detect(dir: &Path) -> Result<Layout> {
+        let Some(root) = layout_root(dir) else {
+            return Ok(Layout::V1);
+        };
+        let path = root.join(LAYOUT_FILE);
+        let version = fs::read_to_string(&path)?;
+        match version.trim() {
+            "1" => Ok(Layout::V1),
+            "2" => Ok(Layout::V2),
+            version => bail!("Unknown layout version {version} in {}", path.display()),
+        }
+    }
+
+    /// The layout to fetch into: new directories use the latest layout, existing unversioned
+    /// directories keep the original one until they are migrated
+    pub fn init(data_dir: &Path) -> Result<Layout> {
+        if layout_root(data_dir).is_some() {
+            return Layout::detect(data_dir);
+        }
+        let is_empty = !data_dir.exists() || fs::read_dir(data_dir)?.next().is_none();
+        if !is_empty {
+            warn!(
+                "{} uses the unversioned layout, run `migrate-layout` to upgrade it",
+                data_dir.display()
+            );
+            return Ok(Layout::V1);
+        }
+        Layout::LATEST.write(data_dir)?;
+        Ok(Layout::LATEST)
+    }
+
+    /// Marks the data directory as using this layout
+    pub fn write(&self, data_dir: &Path) -> Result<()> {
+        fs::create_dir_all(data_dir)?;
+        fs::write(data_dir.join(LAYOUT_FILE), format!("{}\n", self.version()))?;
+        Ok(())
+    }
+
+    /// Detects the layout of a directory passed to a reading command and returns the directory
+    /// of the spot klines if it is the root of a versioned data directory
+    pub fn resolve(dir: &Path) -> Result<(Layout, PathBuf)> {
+        let layout = Layout::detect(dir)?;
+        if layout != Layout::V1 && dir.join(LAYOUT_FILE).is_file() {
+            let dataset = Dataset::new(Market::Spot, DatasetKind::Klines, KlineType::Klines)?;
+            return Ok((layout, dataset.data_dir(dir, layout)));
+        }
+        Ok((layout, dir.to_path_buf()))
+    }
+
+    /// The path of the daily CSV file, the interval is the dataset name for trades (as in the archives)
+    pub fn csv_path(
+        &self,
+        data_dir: &Path,
+        symbol: &str,
+        interval: &str,
+        date: &NaiveDate,
+    ) -> PathBuf {
+        let file_name = format!("{symbol}-{interval}-{}.csv", date.format("%Y-%m-%d"));
+        match self {
+            Layout::V1 => data_dir
+                .join(date.format("%Y/%m/%d").to_string())
+                .join(file_name),
+            Layout::V2 => data_dir
+                .join(interval)
+                .join(symbol)
+                .join(date.format("%Y/%m").to_string())
+                .join(file_name),
+        }
+    }
+
+    /// The CSV files of the matching symbols on the given day
+    pub fn day_files(
+        &self,
+        data_dir: &Path,
+        symbol_filter: &Regex,
+        interval: &str,
+        date: &NaiveDate,
+    ) -> Result<Vec<KlineFile>> {
+        let mut files = vec![];
+        match self {
+            Layout::V1 => {
+                // days without any data have no directory
+                let dir = data_dir.join(date.format("%Y/%m/%d").to_string());
+                if !dir.is_dir() {
+                    return Ok(files);
+                }
+                let file_regex = file_regex(&regex::escape(interval));
+                for file in fs::read_dir(&dir)? {
+                    let file = file?;
+                    let file_name = file.file_name();
+                    // skip other intervals, e.g. resampled files
+                    let Some(matches) = file_regex.captures(file_name.to_str().unwrap_or_default())
+                    else {
+                        continue;
+                    };
+                    let symbol = matches.name("symbol").unwrap().as_str();
+                    if symbol_filter.is_match(symbol) {
+                        files.push(KlineFile {
+                            symbol: symbol.to_string(),
+                            date: *date,
+                            path: file.path(),
+                        });
+                    }
+                }
+            }
+            Layout::V2 => {
+                for symbol in symbols(data_dir, symbol_filter, interval)? {
+                    let path = self.csv_path(data_dir, &symbol, interval, date);
+                    if path.is_file() {
+                        files.push(KlineFile {
+                            symbol,
+                            date: *date,
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// All daily CSV files of the matching symbols and the given interval, sorted by date
+    pub fn kline_files(
+        &self,
+        data_dir: &Path,
+        symbol_filter: &Regex,
+        interval: &str,
+    ) -> Result<Vec<KlineFile>> {
+        let mut files = vec![];
+        match self {
+            Layout::V1 => {
+                for (date, _) in day_directories(data_dir)? {
+                    files.extend(self.day_files(data_dir, symbol_filter, interval, &date)?);
+                }
+            }
+            Layout::V2 => {
+                let file_regex = file_regex(&regex::escape(interval));
+                for symbol in symbols(data_dir, symbol_filter, interval)? {
+                    let symbol_dir = data_dir.join(interval).join(&symbol);
+                    for (_, dir) in month_directories(&symbol_dir)? {
+                        for file in fs::read_dir(dir)? {
+                            let file = file?;
+                            let file_name = file.file_name();
+                            let Some(matches) =
+                                file_regex.captures(file_name.to_str().unwrap_or_default())
+                            else {
+                                continue;
+                            };
+                            if let Some(date) =
+                                matches.name("date").unwrap().as_str().try_parse_date()
+                            {
+                                files.push(KlineFile {
+                                    symbol: symbol.clone(),
+                                    date,
+                                    path: file.path(),
+                                });
+                            }
+                        }
+                    }
+                }
+                files.sort_by_key(|file| file.date);
+            }
+        }
+        Ok(files)
+    }
+
+    /// The intervals (or trade dataset names) with daily CSV files in the data directory
+    pub fn intervals(&self, data_dir: &Path) -> Result<Vec<String>> {
+        let mut intervals = BTreeSet::new();
+        match self {
+            Layout::V1 => {
+                let file_regex = file_regex(r"\w+");
+                for (_, dir) in day_directories(data_dir)? {
+                    for file in fs::read_dir(dir)? {
+                        let file_name = file?.file_name();
+                        if let Some(matches) =
+                            file_regex.captures(file_name.to_str().unwrap_or_default())
+                        {
+                            intervals
+                                .insert(matches.name("interval").unwrap().as_str().to_string());
+                        }
+                    }
+                }
+            }
+            Layout::V2 => {
+                for entry in fs::read_dir(data_dir)? {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let is_dataset = DatasetKind::from_str(&name, false)
+                        .is_ok_and(|kind| kind != DatasetKind::Klines);
+                    if entry.path().is_dir() && (name.parse::<Interval>().is_ok() || is_dataset) {
+                        intervals.insert(name);
+                    }
+                }
+            }
+        }
+        Ok(intervals.into_iter().collect())
+    }
+}
+
+/// The directory containing the layout file, if any
+fn layout_root(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|dir| dir.join(LAYOUT_FILE).is_file())
+}
+
+/// Matches the daily CSV files of the interval, which is a pattern itself to match all intervals
+fn file_regex(interval: &str) -> Regex {
+    Regex::new(&format!(
+        r"^(?P<symbol>\w+)-(?P<interval>{interval})-(?P<date>\d{{4}}-\d{{2}}-\d{{2}})\.csv$"
+    ))
+    .unwrap()
+}
+
+/// The matching symbols with a directory for the interval (layout v2)
+fn symbols(data_dir: &Path, symbol_filter: &Regex, interval: &str) -> Result<Vec<String>> {
+    let interval_dir = data_dir.join(interval);
+    if !interval_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut symbols = vec![];
+    for entry in fs::read_dir(interval_dir)? {
+        let entry = entry?;
+        let symbol = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() && symbol_filter.is_match(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    symbols.sort();
+    Ok(symbols)
+}
+
+/// All `YYYY/MM` directories, sorted by date
+fn month_directories(dir: &Path) -> Result<Vec<(NaiveDate, PathBuf)>> {
+    let mut directories = vec![];
+    for year in fs::read_dir(dir)? {
+        let year = year?;
+        if !year.path().is_dir() {
+            continue;
+        }
+        for month in fs::read_dir(year.path())? {
+            let month = month?;
+            let date = format!(
+                "{}-{}-01",
+                year.file_name().to_string_lossy(),
+                month.file_name().to_string_lossy()
+            );
+            if let Some(date) = date.try_parse_date() {
+                directories.push((date, month.path()));
+            }
+        }
+    }
+    directories.sort();
+    Ok(directories)
+}
+
+/// All `YYYY/MM/DD` directories in the data directory (layout v1), sorted by date
+pub fn day_directories(data_dir: &Path) -> Result<Vec<(NaiveDate, PathBuf)>> {
+    let mut directories = vec![];
+    for (month_start, month) in month_directories(data_dir)? {
+        for day in fs::read_dir(month)? {
+            let day = day?;
+            let date = format!(
+                "{}-{}",
+                month_start.format("%Y-%m"),
+                day.file_name().to_string_lossy()
+            );
+            if let Some(date) = date.try_parse_date() {
+                directories.push((date, day.path()));
+            }
+        }
+    }
+    directories.sort();
+    Ok(directories)
+}
@@ -1,27 +1,63 @@
-use std::time::Instant;
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use anyhow::{Ok, Result};
 use chrono::{Duration, NaiveDate};
 use clap::Parser;
-use cli::Commands;
+use cli::{Commands, FeeArgs};
 use colored::Colorize;
 use crossbeam::channel;
 use date::DateString;
+use fetch_command::FetchMode;
 use indicatif::MultiProgress;
 use indicatif_log_bridge::LogWrapper;
-use log::info;
+use kline_cache::{DataKlineSource, KlineCache};
+use layout::Layout;
+use log::{debug, info, warn};
+use market::Market;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use regex::Regex;
+use strategy::ExecutionPolicy;
+use strategy_config::StrategyConfig;
+use types::Granularity;
 mod cli;
+mod compact_command;
+mod compact_store;
+mod config_file;
 mod date;
+mod download;
+mod export_command;
+mod fees;
 mod fetch_command;
+mod inventory;
+mod inventory_command;
+mod kline_cache;
 mod klines;
+mod layout;
+mod manifest;
+mod market;
+mod metrics;
+mod migrate_layout_command;
+mod optimize_command;
+mod parquet_store;
+mod portfolio;
 mod progress;
+mod resample;
+mod resample_command;
+mod serve_mirror_command;
+mod slippage;
+mod sma_rsi_strategy;
+mod strategy;
+mod strategy_config;
 mod symbols;
 mod test_command;
+mod trades;
 mod trading_signal;
 mod types;
 mod visualize_command;
+mod walk_forward_command;
 
 fn main() -> Result<()> {
     let start = Instant::now();
@@ -40,18 +76,128 @@ fn main() -> Result<()> {
 
     match args.command {
         Commands::Fetch {
-            interval,
-            path,
+            args,
             start_date,
             end_date,
-            symbol,
+            granularity,
+            repair,
         } => {
             let start_date = start_date.try_parse_date();
             let end_date = end_date.try_parse_date();
-            fetch_command::fetch(interval, symbol, start_date, end_date, path, args.verbose)?
+            fetch_command::fetch(
+                args.source(),
+                args.dataset()?,
+                args.interval.clone(),
+                args.symbol.clone(),
+                start_date,
+                end_date,
+                args.path.clone(),
+                args.format,
+                granularity,
+                match repair {
+                    true => FetchMode::Repair,
+                    false => FetchMode::List,
+                },
+                args.retry_policy(),
+                &progress,
+            )?
         }
+        Commands::Sync { args } => fetch_command::fetch(
+            args.source(),
+            args.dataset()?,
+            args.interval.clone(),
+            args.symbol.clone(),
+            None,
+            None,
+            args.path.clone(),
+            args.format,
+            Granularity::Daily,
+            FetchMode::Sync,
+            args.retry_policy(),
+            &progress,
+        )?,
         Commands::Test {
             symbol,
+            interval,
+            strategy,
+            config,
+            fees,
+            execution,
+            output,
+            agg_trades,
+            path,
+            start_date,
+            end_date,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let start_date = start_date.parse_date();
+            let end_date = end_date.parse_date();
+            let config = load_strategy_config(config, &fees, execution, &path)?;
+            let mut klines = DataKlineSource::new(path, symbol_regex.clone(), interval)?;
+            if let Some(agg_trades) = agg_trades {
+                klines.set_agg_trades_dir(agg_trades)?;
+            }
+            let result = test_command::test(
+                &symbol_regex,
+                strategy,
+                &config,
+                &start_date,
+                &end_date,
+                &klines,
+                &progress,
+            )?;
+            if let Some(output) = output {
+                config_file::save(&output, &result)?;
+            }
+        }
+        Commands::Optimize {
+            symbol,
+            interval,
+            strategy,
+            config,
+            fees,
+            execution,
+            ranges,
+            objective,
+            output,
+            path,
+            start_date,
+            end_date,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let start_date = start_date.parse_date();
+            let end_date = end_date.parse_date();
+            let config = load_strategy_config(config, &fees, execution, &path)?;
+            let source = DataKlineSource::new(path, symbol_regex.clone(), interval)?;
+            let klines = KlineCache::load(&source, &start_date, &end_date, &progress)?;
+            let results = optimize_command::optimize(
+                &symbol_regex,
+                strategy,
+                &config,
+                &ranges,
+                objective,
+                &start_date,
+                &end_date,
+                &klines,
+                &progress,
+            )?;
+            optimize_command::print_ranking(&ranges, &results, 10);
+            if let Some(output) = output {
+                optimize_command::write_csv(&output, &ranges, &results)?;
+            }
+        }
+        Commands::WalkForward {
+            symbol,
+            interval,
+            strategy,
+            config,
+            fees,
+            execution,
+            ranges,
+            objective,
+            train_days,
+            test_days,
+            output,
             path,
             start_date,
             end_date,
@@ -59,7 +205,87 @@ fn main() -> Result<()> {
             let symbol_regex = Regex::new(&symbol).unwrap();
             let start_date = start_date.parse_date();
             let end_date = end_date.parse_date();
-            test_command::test(&symbol_regex, &start_date, &end_date, path, &progress)?;
+            let config = load_strategy_config(config, &fees, execution, &path)?;
+            let source = DataKlineSource::new(path, symbol_regex.clone(), interval)?;
+            let klines = KlineCache::load(&source, &start_date, &end_date, &progress)?;
+            let results = walk_forward_command::walk_forward(
+                &symbol_regex,
+                strategy,
+                &config,
+                &ranges,
+                objective,
+                &start_date,
+                &end_date,
+                train_days,
+                test_days,
+                &klines,
+                &progress,
+            )?;
+            if let Some(output) = output {
+                walk_forward_command::write_csv(&output, &ranges, &results)?;
+            }
+        }
+        Commands::Compact {
+            symbol,
+            interval,
+            path,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let (layout, path) = Layout::resolve(&path)?;
+            compact_command::compact(&symbol_regex, &interval, &path, layout, &progress)?;
+        }
+        Commands::Resample {
+            symbol,
+            from,
+            to,
+            path,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let (layout, path) = Layout::resolve(&path)?;
+            resample_command::resample(&symbol_regex, from, &to, &path, layout, &progress)?;
+        }
+        Commands::Export {
+            symbol,
+            interval,
+            format,
+            output,
+            path,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let (layout, path) = Layout::resolve(&path)?;
+            let output = output.unwrap_or_else(|| path.join("parquet"));
+            export_command::export(
+                &symbol_regex,
+                &interval,
+                format,
+                &path,
+                layout,
+                &output,
+                &progress,
+            )?;
+        }
+        Commands::Inventory {
+            symbol,
+            interval,
+            format,
+            path,
+        } => {
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let (layout, path) = Layout::resolve(&path)?;
+            inventory_command::inventory(
+                &symbol_regex,
+                interval.as_deref(),
+                format,
+                &path,
+                layout,
+                &progress,
+            )?;
+        }
+        Commands::MigrateLayout { path } => {
+            migrate_layout_command::migrate_layout(&path, &progress)?;
+        }
+        Commands::ServeMirror { address, path } => {
+            serve_mirror_command::serve_mirror(&address, &path)?;
         }
         Commands::Visualize {
             symbol,
@@ -74,12 +300,19 @@ fn main() -> Result<()> {
         }
         Commands::TestVariants {
             symbol,
+            interval,
+            strategy,
+            config,
+            fees,
+            execution,
+            output,
             path,
             start_date,
             end_date,
         } => {
             let start_date = start_date.parse_date();
             let end_date = end_date.parse_date();
+            let config = load_strategy_config(config, &fees, execution, &path)?;
             let total_days = end_date.signed_duration_since(start_date).num_days();
 
             // TODO: move end_date around too, to test various ranges and increase length to see results early
@@ -87,18 +320,25 @@ fn main() -> Result<()> {
             let start_dates: Vec<NaiveDate> = (0..total_days)
                 .map(|i| start_date + Duration::days(i))
                 .collect();
-            let path = path.as_path();
+
+            // load the klines once and share them across all variants
+            let symbol_regex = Regex::new(&symbol).unwrap();
+            let source = DataKlineSource::new(path, symbol_regex.clone(), interval)?;
+            let klines = KlineCache::load(&source, &start_date, &end_date, &progress)?;
+
             let (sender, receiver) = channel::unbounded();
             start_dates.par_iter().for_each(|start_date| {
-                let symbol_regex = Regex::new(&symbol).unwrap();
                 let performance = test_command::test(
                     &symbol_regex,
+                    strategy,
+                    &config,
                     start_date,
                     &end_date,
-                    path.to_path_buf(),
+                    &klines,
                     &progress,
                 )
-                .unwrap();
+                .unwrap()
+                .performance;
                 sender.send(performance).unwrap();
             });
             drop(sender);
@@ -111,7 +351,20 @@ fn main() -> Result<()> {
             } else {
                 format!("{}%", average_performance.round()).red()
             };
-            info!("Average performance from {start_date} to {end_date} with {variations} variations: {performance_label}",)
+            info!("Average performance from {start_date} to {end_date} with {variations} variations: {performance_label}",);
+
+            if let Some(output) = output {
+                let result = test_command::VariantsResult {
+                    strategy,
+                    symbol_filter: symbol,
+                    start_date,
+                    end_date,
+                    variations: variations as usize,
+                    average_performance,
+                    config,
+                };
+                config_file::save(&output, &result)?;
+            }
         }
     }
 
@@ -119,3 +372,31 @@ fn main() -> Result<()> {
     info!("Finished after {:?}", duration);
     Ok(())
 }
+
+fn load_strategy_config(
+    path: Option<PathBuf>,
+    fees: &FeeArgs,
+    execution: Option<ExecutionPolicy>,
+    data_dir: &Path,
+) -> Result<StrategyConfig> {
+    let mut config: StrategyConfig = match path {
+        Some(path) => config_file::load(&path)?,
+        None => StrategyConfig::default(),
+    };
+    if let Some(fee) = config.fee.take() {
+        warn!("`fee` is deprecated, use `maker` and `taker` in `[fees]` instead");
+        config.fees.maker = Some(fee);
+        config.fees.taker = Some(fee);
+    }
+    fees.apply(&mut config.fees);
+    config
+        .fees
+        .market
+        .get_or_insert_with(|| Market::from_path(data_dir));
+    config.slippage.validate()?;
+    if let Some(execution) = execution {
+        config.execution = execution;
+    }
+    debug!("Strategy config: {:?}", config);
+    Ok(config)
+}
@@ -0,0 +1,84 @@
+use crate::{config_file, market::Dataset};
+use anyhow::{Ok, Result};
+use chrono::NaiveDate;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// The newest day fetched per market, dataset, symbol and interval, stored in the data directory
+/// so that `sync` only lists the archives after it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchManifest {
+    /// The newest day by listing prefix of the daily archives, e.g. `data/spot/daily/klines/BTCUSDT/1m/`
+    pub last_days: BTreeMap<String, NaiveDate>,
+    /// Incomplete days whose archives have no more rows, by listing prefix, skipped by `repair`
+    pub short_days: BTreeMap<String, BTreeSet<NaiveDate>>,
+    /// The monthly archives extracted completely by path, e.g.
+    /// `data/spot/monthly/klines/BTCUSDT/1m/BTCUSDT-1m-2023-08.zip`, as the months a symbol was
+    /// listed or delisted in have no file for every day
+    pub monthly_archives: BTreeSet<String>,
+}
+
+impl FetchManifest {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("fetch-manifest.toml")
+    }
+
+    /// Loads the manifest, which is empty before the first fetch
+    pub fn load(path: &Path) -> Result<FetchManifest> {
+        if !path.is_file() {
+            return Ok(FetchManifest::default());
+        }
+        config_file::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        config_file::save(path, self)
+    }
+
+    pub fn last_day(&self, dataset: Dataset, symbol: &str, interval: &str) -> Option<NaiveDate> {
+        let prefix = dataset.symbol_prefix("daily", symbol, interval);
+        self.last_days.get(&prefix).copied()
+    }
+
+    /// Remembers the day if it is newer than the last one
+    pub fn update(&mut self, dataset: Dataset, symbol: &str, interval: &str, day: NaiveDate) {
+        let prefix = dataset.symbol_prefix("daily", symbol, interval);
+        let last_day = self.last_days.entry(prefix).or_insert(day);
+        *last_day = (*last_day).max(day);
+    }
+
+    pub fn is_short(&self, dataset: Dataset, symbol: &str, interval: &str, day: NaiveDate) -> bool {
+        let prefix = dataset.symbol_prefix("daily", symbol, interval);
+        self.short_days
+            .get(&prefix)
+            .is_some_and(|days| days.contains(&day))
+    }
+
+    /// Remembers that the archive of the day is incomplete, too
+    pub fn add_short(&mut self, dataset: Dataset, symbol: &str, interval: &str, day: NaiveDate) {
+        let prefix = dataset.symbol_prefix("daily", symbol, interval);
+        self.short_days.entry(prefix).or_default().insert(day);
+    }
+
+    pub fn is_extracted(&self, url: &str) -> bool {
+        self.monthly_archives.contains(&archive_path(url))
+    }
+
+    /// Remembers that the monthly archive was extracted completely
+    pub fn add_extracted(&mut self, url: &str) {
+        self.monthly_archives.insert(archive_path(url));
+    }
+}
+
+/// The path of an archive URL, the same for the Binance bucket and mirrors
+fn archive_path(url: &str) -> String {
+    match Url::parse(url) {
+        Result::Ok(url) => url.path().trim_start_matches('/').to_string(),
+        Err(_) => url.to_string(),
+    }
+}
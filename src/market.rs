@@ -0,0 +1,154 @@
+use crate::layout::Layout;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The Binance market to fetch data from
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Market {
+    Spot,
+    /// USD-M futures
+    Um,
+    /// COIN-M futures
+    Cm,
+}
+
+impl Market {
+    /// The market of a data directory like `./data/futures/um` or `./data/futures/cm/mark-price`,
+    /// spot otherwise
+    pub fn from_path(path: &Path) -> Market {
+        let components: Vec<_> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        let futures = components
+            .windows(2)
+            .find(|pair| pair[0] == "futures")
+            .map(|pair| pair[1].to_string());
+        match futures.as_deref() {
+            Some("um") => Market::Um,
+            Some("cm") => Market::Cm,
+            _ => Market::Spot,
+        }
+    }
+}
+
+/// The kind of data to fetch
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DatasetKind {
+    Klines,
+    /// Trades aggregated by price and taker side
+    #[value(name = "aggTrades")]
+    AggTrades,
+    Trades,
+}
+
+/// The kind of klines to fetch (mark and index price klines only exist for futures)
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum KlineType {
+    Klines,
+    MarkPrice,
+    IndexPrice,
+}
+
+/// A market and dataset, e.g. the mark price klines or the trades of USD-M futures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dataset {
+    pub market: Market,
+    pub kind: DatasetKind,
+    pub kline_type: KlineType,
+}
+
+impl Dataset {
+    pub fn new(market: Market, kind: DatasetKind, kline_type: KlineType) -> Result<Self> {
+        if kline_type != KlineType::Klines {
+            let kline_type = kline_type.to_possible_value().unwrap();
+            if market == Market::Spot {
+                bail!(
+                    "{} klines are only available for futures",
+                    kline_type.get_name()
+                );
+            }
+            if kind != DatasetKind::Klines {
+                bail!(
+                    "--kline-type {} requires --dataset klines",
+                    kline_type.get_name()
+                );
+            }
+        }
+        Ok(Dataset {
+            market,
+            kind,
+            kline_type,
+        })
+    }
+
+    /// The name Binance uses in paths and file names, e.g. `markPriceKlines` or `aggTrades`
+    fn name(&self) -> &'static str {
+        match (self.kind, self.kline_type) {
+            (DatasetKind::Klines, KlineType::Klines) => "klines",
+            (DatasetKind::Klines, KlineType::MarkPrice) => "markPriceKlines",
+            (DatasetKind::Klines, KlineType::IndexPrice) => "indexPriceKlines",
+            (DatasetKind::AggTrades, _) => "aggTrades",
+            (DatasetKind::Trades, _) => "trades",
+        }
+    }
+
+    /// The listing prefix, e.g. `data/futures/um/daily/markPriceKlines/`
+    pub fn prefix(&self, period: &str) -> String {
+        let market = match self.market {
+            Market::Spot => "spot",
+            Market::Um => "futures/um",
+            Market::Cm => "futures/cm",
+        };
+        format!("data/{market}/{period}/{}/", self.name())
+    }
+
+    /// The listing prefix of a symbol, klines are listed per interval
+    pub fn symbol_prefix(&self, period: &str, symbol: &str, interval: &str) -> String {
+        match self.kind {
+            DatasetKind::Klines => format!("{}{symbol}/{interval}/", self.prefix(period)),
+            _ => format!("{}{symbol}/", self.prefix(period)),
+        }
+    }
+
+    /// The file name without date, e.g. `BTCUSDT-1m` or `BTCUSDT-aggTrades`
+    pub fn file_stem(&self, symbol: &str, interval: &str) -> String {
+        format!("{symbol}-{}", self.file_interval(interval))
+    }
+
+    /// The interval in file names, trades use the dataset name instead
+    pub fn file_interval<'a>(&self, interval: &'a str) -> &'a str {
+        match self.kind {
+            DatasetKind::Klines => interval,
+            _ => self.name(),
+        }
+    }
+
+    /// The directory of the dataset in the data directory. In layout v1 spot klines are stored
+    /// in the data directory itself and everything else in a subdirectory, e.g. `futures/um/klines`,
+    /// `futures/cm/mark-price` or `spot/aggTrades`. In layout v2 every dataset is stored in its
+    /// market directory, e.g. `spot` or `futures/um`, and mark and index price klines in
+    /// `futures/um/mark-price` or `futures/um/index-price`.
+    pub fn data_dir(&self, data_dir: &Path, layout: Layout) -> PathBuf {
+        let market = match self.market {
+            Market::Spot if self.kind == DatasetKind::Klines && layout == Layout::V1 => {
+                return data_dir.to_path_buf()
+            }
+            Market::Spot => "spot",
+            Market::Um => "futures/um",
+            Market::Cm => "futures/cm",
+        };
+        let name = match self.kind {
+            DatasetKind::Klines => self.kline_type.to_possible_value().unwrap(),
+            kind => kind.to_possible_value().unwrap(),
+        };
+        match layout {
+            Layout::V1 => data_dir.join(market).join(name.get_name()),
+            Layout::V2 if self.kline_type == KlineType::Klines => data_dir.join(market),
+            Layout::V2 => data_dir.join(market).join(name.get_name()),
+        }
+    }
+}
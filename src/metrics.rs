@@ -0,0 +1,36 @@
+/// Chains two returns in percent, e.g. 10% followed by 10% is 21%
+pub fn compound(performance: f64, next_performance: f64) -> f64 {
+    ((1.0 + performance / 100.0) * (1.0 + next_performance / 100.0) - 1.0) * 100.0
+}
+
+/// Annualized Sharpe ratio of a daily performance curve (in percent), assuming a risk free rate of 0.
+/// It is 0 once the equity is gone, as the daily returns are undefined then.
+pub fn sharpe_ratio(daily_performance: &[f64]) -> f64 {
+    let returns: Vec<f64> = daily_performance
+        .windows(2)
+        .map(|days| (100.0 + days[1]) / (100.0 + days[0]) * 100.0 - 100.0)
+        .collect();
+    if returns.len() < 2 || returns.iter().any(|r| !r.is_finite()) {
+        return 0.0;
+    }
+
+    let count = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / count;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (count - 1.0);
+    let deviation = variance.sqrt();
+    if deviation == 0.0 {
+        return 0.0;
+    }
+    mean / deviation * 365f64.sqrt()
+}
+
+/// Largest drop from a previous peak of a daily performance curve, in percent of the peak equity
+pub fn max_drawdown(daily_performance: &[f64]) -> f64 {
+    let mut peak = 0.0f64;
+    let mut max_drawdown = 0.0f64;
+    for performance in daily_performance {
+        peak = peak.max(*performance);
+        max_drawdown = max_drawdown.max(100.0 * (peak - performance) / (100.0 + peak));
+    }
+    max_drawdown
+}
@@ -0,0 +1,102 @@
+use crate::{
+    layout::{self, Layout},
+    market::{Dataset, DatasetKind, KlineType, Market},
+    progress,
+};
+use anyhow::{Context, Ok, Result};
+use clap::ValueEnum;
+use indicatif::MultiProgress;
+use log::info;
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Moves the files of a data directory into the latest layout and marks it as migrated
+pub fn migrate_layout(data_dir: &Path, progress: &MultiProgress) -> Result<()> {
+    let layout = Layout::detect(data_dir)?;
+    if layout == Layout::LATEST {
+        info!("{} uses the latest layout already", data_dir.display());
+        return Ok(());
+    }
+
+    let scan_progress = progress::progress_bar(progress, "Scanning data directory");
+    let file_regex =
+        Regex::new(r"^(?P<symbol>\w+)-(?P<interval>\w+)-\d{4}-\d{2}-\d{2}\.csv$").unwrap();
+    let mut moves: Vec<(PathBuf, PathBuf)> = vec![];
+    for dataset in datasets() {
+        let from = dataset.data_dir(data_dir, layout);
+        let to = dataset.data_dir(data_dir, Layout::LATEST);
+        if !from.is_dir() {
+            continue;
+        }
+
+        for (date, dir) in layout::day_directories(&from)? {
+            for file in fs::read_dir(dir)? {
+                let file = file?;
+                let file_name = file.file_name();
+                let Some(matches) = file_regex.captures(file_name.to_str().unwrap_or_default())
+                else {
+                    continue;
+                };
+                let symbol = matches.name("symbol").unwrap().as_str();
+                let interval = matches.name("interval").unwrap().as_str();
+                let path = Layout::LATEST.csv_path(&to, symbol, interval, &date);
+                moves.push((file.path(), path));
+            }
+        }
+
+        // the compact store and Parquet files are kept next to the klines
+        if dataset.kind == DatasetKind::Klines && from != to {
+            for name in ["compact", "parquet"] {
+                if from.join(name).is_dir() {
+                    moves.push((from.join(name), to.join(name)));
+                }
+            }
+        }
+    }
+    scan_progress.finish_and_clear();
+
+    let total_moves = moves.len();
+    let move_progress = progress::progress_bar(progress, "Moving files");
+    for (index, (from, to)) in moves.iter().enumerate() {
+        fs::create_dir_all(to.parent().unwrap())?;
+        fs::rename(from, to)
+            .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+        remove_empty_dirs(from.parent().unwrap(), data_dir);
+        move_progress.set_message(format!("Moving files ({}/{total_moves})", index + 1));
+    }
+    move_progress.finish_and_clear();
+
+    Layout::LATEST.write(data_dir)?;
+    info!(
+        "Moved {total_moves} files and directories of {} into the latest layout",
+        data_dir.display()
+    );
+    Ok(())
+}
+
+/// All datasets that can be fetched
+fn datasets() -> Vec<Dataset> {
+    let mut datasets = vec![];
+    for market in Market::value_variants() {
+        for kind in DatasetKind::value_variants() {
+            for kline_type in KlineType::value_variants() {
+                if let Result::Ok(dataset) = Dataset::new(*market, *kind, *kline_type) {
+                    datasets.push(dataset);
+                }
+            }
+        }
+    }
+    datasets
+}
+
+/// Removes the directory and its parents up to the data directory as long as they are empty
+fn remove_empty_dirs(dir: &Path, data_dir: &Path) {
+    for dir in dir.ancestors().take_while(|dir| *dir != data_dir) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
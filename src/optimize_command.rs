@@ -0,0 +1,240 @@
+use crate::{
+    kline_cache::KlineSource,
+    strategy::StrategyKind,
+    strategy_config::StrategyConfig,
+    test_command::{self, TestResult},
+};
+use anyhow::{Ok, Result};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use colored::Colorize;
+use csv::Writer;
+use indicatif::MultiProgress;
+use log::info;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use std::{path::PathBuf, str::FromStr};
+
+/// Strategy parameters that can be optimized
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Parameter {
+    TakeProfit,
+    MaxAgeDays,
+    RsiPeriod,
+    RsiThreshold,
+    /// Maker and taker fee
+    Fee,
+    MakerFee,
+    TakerFee,
+    PositionSize,
+}
+
+impl Parameter {
+    pub fn name(&self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+
+    pub fn apply(&self, config: &mut StrategyConfig, value: f64) {
+        match self {
+            Parameter::TakeProfit => config.exits.take_profit = value,
+            Parameter::MaxAgeDays => config.exits.max_age_days = value.round() as i64,
+            Parameter::RsiPeriod => config.indicators.rsi.period = value.round() as usize,
+            Parameter::RsiThreshold => config.indicators.rsi.threshold = value,
+            Parameter::Fee => {
+                config.fees.maker = Some(value);
+                config.fees.taker = Some(value);
+            }
+            Parameter::MakerFee => config.fees.maker = Some(value),
+            Parameter::TakerFee => config.fees.taker = Some(value),
+            Parameter::PositionSize => config.portfolio.position_size = value,
+        }
+    }
+}
+
+/// A parameter range, e.g. `take-profit=2..10:0.5` (the step defaults to 1)
+#[derive(Debug, Clone)]
+pub struct ParameterRange {
+    pub parameter: Parameter,
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl FromStr for ParameterRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = format!("Invalid range {s:?}, expected <parameter>=<start>..<end>[:<step>]");
+        let (parameter, range) = s.split_once('=').ok_or(&usage)?;
+        let (range, step) = range.split_once(':').unwrap_or((range, "1"));
+        let (start, end) = range.split_once("..").ok_or(&usage)?;
+
+        let parameter = Parameter::from_str(parameter, true)?;
+        let parse = |value: &str| value.trim().parse::<f64>().map_err(|_| usage.clone());
+        let (start, end, step) = (parse(start)?, parse(end)?, parse(step)?);
+        if step <= 0.0 || end < start {
+            return Err(usage);
+        }
+
+        Result::Ok(ParameterRange {
+            parameter,
+            start,
+            end,
+            step,
+        })
+    }
+}
+
+impl ParameterRange {
+    /// All values from start to end (inclusive)
+    pub fn values(&self) -> Vec<f64> {
+        let steps = ((self.end - self.start) / self.step + 1e-9).floor() as usize;
+        (0..=steps)
+            .map(|i| self.start + self.step * i as f64)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Objective {
+    /// Highest total performance
+    TotalReturn,
+    /// Highest annualized Sharpe ratio of the daily performance
+    Sharpe,
+    /// Smallest maximum drawdown
+    MaxDrawdown,
+}
+
+impl Objective {
+    /// Returns a score where higher is better
+    pub fn score(&self, result: &TestResult) -> f64 {
+        match self {
+            Objective::TotalReturn => result.performance,
+            Objective::Sharpe => result.sharpe_ratio,
+            Objective::MaxDrawdown => -result.max_drawdown,
+        }
+    }
+}
+
+pub struct OptimizationResult {
+    pub values: Vec<f64>,
+    pub result: TestResult,
+}
+
+/// Every combination of the given parameter ranges applied to the base config
+pub fn combinations(
+    base_config: &StrategyConfig,
+    ranges: &[ParameterRange],
+) -> Vec<(Vec<f64>, StrategyConfig)> {
+    let mut combinations = vec![(vec![], base_config.clone())];
+    for range in ranges {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|(values, config)| {
+                range.values().into_iter().map(move |value| {
+                    let mut config = config.clone();
+                    range.parameter.apply(&mut config, value);
+                    let mut values = values.clone();
+                    values.push(value);
+                    (values, config)
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// Tests all parameter combinations and returns them ranked by the objective, best first
+#[allow(clippy::too_many_arguments)]
+pub fn optimize(
+    symbol_filter: &Regex,
+    strategy: StrategyKind,
+    base_config: &StrategyConfig,
+    ranges: &[ParameterRange],
+    objective: Objective,
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+    klines: &dyn KlineSource,
+    progress: &MultiProgress,
+) -> Result<Vec<OptimizationResult>> {
+    let combinations = combinations(base_config, ranges);
+    info!(
+        "Testing {} parameter combinations from {start_date} to {end_date}",
+        combinations.len()
+    );
+
+    let mut results = combinations
+        .into_par_iter()
+        .map(|(values, config)| {
+            let result = test_command::test(
+                symbol_filter,
+                strategy,
+                &config,
+                start_date,
+                end_date,
+                klines,
+                progress,
+            )?;
+            Ok(OptimizationResult { values, result })
+        })
+        .collect::<Result<Vec<OptimizationResult>>>()?;
+
+    results.sort_by(|a, b| {
+        objective
+            .score(&b.result)
+            .total_cmp(&objective.score(&a.result))
+    });
+    Ok(results)
+}
+
+pub fn write_csv(
+    path: &PathBuf,
+    ranges: &[ParameterRange],
+    results: &[OptimizationResult],
+) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    let mut headers: Vec<String> = ranges.iter().map(|range| range.parameter.name()).collect();
+    headers.extend(
+        ["performance", "sharpe_ratio", "max_drawdown", "trades"]
+            .iter()
+            .map(|header| header.to_string()),
+    );
+    writer.write_record(&headers)?;
+
+    for optimization in results {
+        let result = &optimization.result;
+        let mut record: Vec<String> = optimization.values.iter().map(f64::to_string).collect();
+        record.push(result.performance.to_string());
+        record.push(result.sharpe_ratio.to_string());
+        record.push(result.max_drawdown.to_string());
+        record.push(result.trades.to_string());
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn print_ranking(ranges: &[ParameterRange], results: &[OptimizationResult], limit: usize) {
+    for (rank, optimization) in results.iter().take(limit).enumerate() {
+        let result = &optimization.result;
+        let parameters = ranges
+            .iter()
+            .zip(&optimization.values)
+            .map(|(range, value)| format!("{}={value}", range.parameter.name()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let performance = if result.performance > 0.0 {
+            format!("{}%", result.performance.round()).green()
+        } else {
+            format!("{}%", result.performance.round()).red()
+        };
+        info!(
+            "#{} {}: performance: {performance}, sharpe: {:.2}, max drawdown: {:.1}%, trades: {}",
+            rank + 1,
+            parameters.yellow(),
+            result.sharpe_ratio,
+            result.max_drawdown,
+            result.trades
+        );
+    }
+}
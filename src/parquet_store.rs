@@ -0,0 +1,109 @@
+use crate::klines::{self, KlineRecord};
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use parquet::{
+    basic::Compression,
+    data_type::{DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Hive style partitioned path, e.g. `parquet/symbol=BTCUSDT/interval=1m/date=2021-01-01/data.parquet`
+pub fn parquet_path(base_dir: &Path, symbol: &str, interval: &str, date: &NaiveDate) -> PathBuf {
+    base_dir
+        .join(format!("symbol={symbol}"))
+        .join(format!("interval={interval}"))
+        .join(format!("date={}", date.format("%Y-%m-%d")))
+        .join("data.parquet")
+}
+
+enum Column {
+    Int64(fn(&KlineRecord) -> i64),
+    Double(fn(&KlineRecord) -> f64),
+}
+
+fn column(name: &str) -> Result<Column> {
+    let column = match name {
+        "open_time" => Column::Int64(|record| record.open_time),
+        "open" => Column::Double(|record| record.open),
+        "high" => Column::Double(|record| record.high),
+        "low" => Column::Double(|record| record.low),
+        "close" => Column::Double(|record| record.close),
+        "volume" => Column::Double(|record| record.volume),
+        "close_time" => Column::Int64(|record| record.close_time),
+        "quote_asset_volume" => Column::Double(|record| record.quote_asset_volume),
+        "number_of_trades" => Column::Int64(|record| record.number_of_trades),
+        "taker_buy_base_asset_volume" => {
+            Column::Double(|record| record.taker_buy_base_asset_volume)
+        }
+        "taker_buy_quote_asset_volume" => {
+            Column::Double(|record| record.taker_buy_quote_asset_volume)
+        }
+        _ => bail!("Unknown kline column {name}"),
+    };
+    Ok(column)
+}
+
+/// Writes the klines as Parquet file with the same columns as the CSV files
+pub fn write(path: &Path, records: &[KlineRecord]) -> Result<()> {
+    let headers = klines::csv_headers();
+    let columns = headers
+        .iter()
+        .map(|name| Ok((name, column(name)?)))
+        .collect::<Result<Vec<(&str, Column)>>>()?;
+
+    let fields = columns
+        .iter()
+        .map(|(name, column)| match column {
+            Column::Int64(_) => format!("REQUIRED INT64 {name};"),
+            Column::Double(_) => format!("REQUIRED DOUBLE {name};"),
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    let schema = Arc::new(parse_message_type(&format!(
+        "message kline {{ {fields} }}"
+    ))?);
+    let properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // write to a temporary file first, so an interrupted fetch never leaves a partial file
+    let temp_path = path.with_extension("parquet.tmp");
+    let mut writer = SerializedFileWriter::new(File::create(&temp_path)?, schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+    for (_, column) in &columns {
+        let Some(mut column_writer) = row_group.next_column()? else {
+            bail!("Parquet schema has less columns than expected");
+        };
+        match column {
+            Column::Int64(value) => {
+                let values: Vec<i64> = records.iter().map(value).collect();
+                column_writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Double(value) => {
+                let values: Vec<f64> = records.iter().map(value).collect();
+                column_writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
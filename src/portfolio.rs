@@ -0,0 +1,208 @@
+use crate::{
+    fees::{FeeModel, Liquidity},
+    klines::Kline,
+    slippage::SlippageModel,
+    strategy_config::PortfolioConfig,
+};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// An open position of a symbol
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub quantity: f64,
+    pub entry_price: f64,
+    pub entry_time: NaiveDateTime,
+    /// Fee paid on entry in the quote asset
+    pub entry_fee: f64,
+    pub entry_liquidity: Liquidity,
+    /// Cost of the slippage on entry in the quote asset
+    pub entry_slippage: f64,
+    /// The latest known price, to value the position
+    pub price: f64,
+}
+
+impl Position {
+    /// Profit or loss at the latest known price in the quote asset, including the entry fee
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.quantity * (self.price - self.entry_price) - self.entry_fee
+    }
+}
+
+/// A closed position, all amounts in the quote asset, the prices include the slippage
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub symbol: String,
+    pub entry_time: NaiveDateTime,
+    pub exit_time: NaiveDateTime,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    pub quantity: f64,
+    pub entry_fee: f64,
+    pub exit_fee: f64,
+    pub entry_liquidity: Liquidity,
+    pub exit_liquidity: Liquidity,
+    pub entry_slippage: f64,
+    pub exit_slippage: f64,
+}
+
+impl Trade {
+    pub fn fees(&self) -> f64 {
+        self.entry_fee + self.exit_fee
+    }
+
+    /// Profit or loss after fees
+    pub fn pnl(&self) -> f64 {
+        self.quantity * (self.exit_price - self.entry_price) - self.fees()
+    }
+
+    /// Return on the invested amount (including the entry fee) in percent
+    pub fn return_percent(&self) -> f64 {
+        100.0 * self.pnl() / (self.quantity * self.entry_price + self.entry_fee)
+    }
+}
+
+/// An account in the quote asset whose cash is shared by all symbols
+pub struct Portfolio {
+    pub initial_capital: f64,
+    pub cash: f64,
+    pub positions: HashMap<String, Position>,
+    /// Percent of the equity to invest per position
+    pub position_size: f64,
+    pub fees: FeeModel,
+    pub slippage: SlippageModel,
+    /// Fees paid on all buys and sells
+    pub total_fees: f64,
+    /// Cost of the slippage of all buys and sells
+    pub total_slippage: f64,
+    /// Profit or loss of all closed positions after fees
+    pub realized_pnl: f64,
+}
+
+impl Portfolio {
+    pub fn new(config: &PortfolioConfig, fees: FeeModel, slippage: SlippageModel) -> Portfolio {
+        Portfolio {
+            initial_capital: config.initial_capital,
+            cash: config.initial_capital,
+            positions: HashMap::new(),
+            position_size: config.position_size,
+            fees,
+            slippage,
+            total_fees: 0.0,
+            total_slippage: 0.0,
+            realized_pnl: 0.0,
+        }
+    }
+
+    /// Cash plus the value of all open positions
+    pub fn equity(&self) -> f64 {
+        self.cash
+            + self
+                .positions
+                .values()
+                .map(|position| position.quantity * position.price)
+                .sum::<f64>()
+    }
+
+    /// Total return on the initial capital in percent, compounded as positions are sized by
+    /// the current equity
+    pub fn performance(&self) -> f64 {
+        100.0 / self.initial_capital * self.equity() - 100.0
+    }
+
+    /// Profit or loss of all open positions at their latest known price
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.positions.values().map(Position::unrealized_pnl).sum()
+    }
+
+    /// Updates the price the open position of the symbol is valued at
+    pub fn mark(&mut self, symbol: &str, price: f64) {
+        if let Some(position) = self.positions.get_mut(symbol) {
+            position.price = price;
+        }
+    }
+
+    /// The slippage in percent of a market order of `amount` in the quote asset
+    fn slippage(&self, liquidity: Liquidity, price: f64, amount: f64, kline: &Kline) -> f64 {
+        match liquidity {
+            Liquidity::Maker => 0.0,
+            Liquidity::Taker => self.slippage.percent(price, amount, kline),
+        }
+    }
+
+    /// Opens a position sized by the equity, limited by the cash left. The kline determines the
+    /// slippage. Returns the fill price or `None` if there is no cash left.
+    pub fn buy(
+        &mut self,
+        symbol: &str,
+        price: f64,
+        timestamp: NaiveDateTime,
+        liquidity: Liquidity,
+        kline: &Kline,
+    ) -> Option<f64> {
+        let amount = (self.equity() * self.position_size / 100.0).min(self.cash);
+        if amount <= 0.0 || self.positions.contains_key(symbol) {
+            return None;
+        }
+        let quoted_price = price;
+        let price = price * (1.0 + self.slippage(liquidity, price, amount, kline) / 100.0);
+        let quantity = amount / (price * (1.0 + self.fees.rate(liquidity) / 100.0));
+        let entry_fee = amount - quantity * price;
+        let entry_slippage = quantity * (price - quoted_price);
+        self.cash -= amount;
+        self.total_fees += entry_fee;
+        self.total_slippage += entry_slippage;
+        self.positions.insert(
+            symbol.to_string(),
+            Position {
+                quantity,
+                entry_price: price,
+                entry_time: timestamp,
+                entry_fee,
+                entry_liquidity: liquidity,
+                entry_slippage,
+                price,
+            },
+        );
+        Some(price)
+    }
+
+    /// Closes the position of the symbol and returns the trade
+    pub fn sell(
+        &mut self,
+        symbol: &str,
+        price: f64,
+        timestamp: NaiveDateTime,
+        liquidity: Liquidity,
+        kline: &Kline,
+    ) -> Option<Trade> {
+        let position = self.positions.remove(symbol)?;
+        let slippage = self.slippage(liquidity, price, position.quantity * price, kline);
+        let quoted_price = price;
+        let price = price * (1.0 - slippage / 100.0);
+        let exit_slippage = position.quantity * (quoted_price - price);
+        self.total_slippage += exit_slippage;
+        let value = position.quantity * price;
+        let exit_fee = value * self.fees.rate(liquidity) / 100.0;
+        self.cash += value - exit_fee;
+        self.total_fees += exit_fee;
+
+        let trade = Trade {
+            symbol: symbol.to_string(),
+            entry_time: position.entry_time,
+            exit_time: timestamp,
+            entry_price: position.entry_price,
+            exit_price: price,
+            quantity: position.quantity,
+            entry_fee: position.entry_fee,
+            exit_fee,
+            entry_liquidity: position.entry_liquidity,
+            exit_liquidity: liquidity,
+            entry_slippage: position.entry_slippage,
+            exit_slippage,
+        };
+        self.realized_pnl += trade.pnl();
+        Some(trade)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::klines::{self, KlineRecord};
+use chrono::Duration;
+use std::{fmt, str::FromStr};
+
+/// A kline interval like `1m`, `15m`, `4h` or `1d`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    count: i64,
+    unit: char,
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = format!("Invalid interval {s:?}, expected e.g. 1m, 15m, 4h or 1d");
+        let unit = s.chars().last().ok_or(&usage)?;
+        let count = s[..s.len() - unit.len_utf8()]
+            .parse::<i64>()
+            .map_err(|_| usage.clone())?;
+        if count < 1 || !matches!(unit, 's' | 'm' | 'h' | 'd') {
+            return Err(usage);
+        }
+        Result::Ok(Interval { count, unit })
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.count, self.unit)
+    }
+}
+
+impl Interval {
+    pub fn milliseconds(&self) -> i64 {
+        let unit = match self.unit {
+            's' => 1_000,
+            'm' => 60_000,
+            'h' => 3_600_000,
+            _ => 86_400_000,
+        };
+        self.count * unit
+    }
+
+    /// The number of klines covering the duration, rounded and at least one
+    pub fn klines(&self, duration: Duration) -> usize {
+        let klines = duration.num_milliseconds() as f64 / self.milliseconds() as f64;
+        (klines.round() as usize).max(1)
+    }
+
+    /// Whether bars of the interval can be merged from klines of `from` without spanning
+    /// multiple daily files
+    pub fn is_resamplable_from(&self, from: Interval) -> bool {
+        let day = Interval {
+            count: 1,
+            unit: 'd',
+        };
+        self.milliseconds() % from.milliseconds() == 0
+            && day.milliseconds() % self.milliseconds() == 0
+    }
+
+    /// The open time of the bar containing the timestamp, bars are aligned to UTC midnight
+    pub fn bar_open_time(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.milliseconds())
+    }
+}
+
+/// Merges consecutive klines into bars of a higher interval
+pub struct Resampler {
+    interval: Interval,
+    current: Option<KlineRecord>,
+}
+
+impl Resampler {
+    pub fn new(interval: Interval) -> Resampler {
+        Resampler {
+            interval,
+            current: None,
+        }
+    }
+
+    /// Adds the next kline and returns the previous bar once a new one begins. The bars are in
+    /// milliseconds, also for klines in microseconds.
+    pub fn push(&mut self, kline: &KlineRecord) -> Option<KlineRecord> {
+        let open_time = self
+            .interval
+            .bar_open_time(klines::milliseconds(kline.open_time));
+        match &mut self.current {
+            Some(bar) if bar.open_time == open_time => {
+                bar.high = bar.high.max(kline.high);
+                bar.low = bar.low.min(kline.low);
+                bar.close = kline.close;
+                bar.volume += kline.volume;
+                bar.quote_asset_volume += kline.quote_asset_volume;
+                bar.number_of_trades += kline.number_of_trades;
+                bar.taker_buy_base_asset_volume += kline.taker_buy_base_asset_volume;
+                bar.taker_buy_quote_asset_volume += kline.taker_buy_quote_asset_volume;
+                None
+            }
+            _ => {
+                let bar = KlineRecord {
+                    open_time,
+                    close_time: open_time + self.interval.milliseconds() - 1,
+                    ..kline.clone()
+                };
+                self.current.replace(bar)
+            }
+        }
+    }
+
+    /// Returns the last (possibly incomplete) bar
+    pub fn finish(&mut self) -> Option<KlineRecord> {
+        self.current.take()
+    }
+}
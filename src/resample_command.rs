@@ -0,0 +1,77 @@
+use crate::{
+    klines,
+    layout::Layout,
+    progress,
+    resample::{Interval, Resampler},
+};
+use anyhow::{bail, Ok, Result};
+use indicatif::MultiProgress;
+use log::info;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use regex::Regex;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Resamples the daily CSV files into higher intervals and writes them next to the source files,
+/// skipping files that exist already
+pub fn resample(
+    symbol_filter: &Regex,
+    from: Interval,
+    to: &[Interval],
+    data_dir: &Path,
+    layout: Layout,
+    progress: &MultiProgress,
+) -> Result<()> {
+    for interval in to {
+        if !interval.is_resamplable_from(from) {
+            bail!("{interval} has to be a multiple of {from} and divide a day");
+        }
+    }
+
+    let files = layout.kline_files(data_dir, symbol_filter, &from.to_string())?;
+    let total_files = files.len();
+    let resample_progress = progress::progress_bar(progress, "Resampling klines");
+    let resampled_files = AtomicUsize::new(0);
+
+    files
+        .into_par_iter()
+        .map(|file| {
+            let mut records = None;
+            for interval in to {
+                let path =
+                    layout.csv_path(data_dir, &file.symbol, &interval.to_string(), &file.date);
+                if path.is_file() {
+                    continue;
+                }
+                if records.is_none() {
+                    records = Some(klines::read_kline_records(&file.path)?);
+                }
+
+                let mut resampler = Resampler::new(*interval);
+                let mut bars: Vec<_> = records
+                    .iter()
+                    .flatten()
+                    .filter_map(|record| resampler.push(record))
+                    .collect();
+                bars.extend(resampler.finish());
+                klines::write_kline_records(&path, &bars)?;
+            }
+
+            let resampled_files = resampled_files.fetch_add(1, Ordering::Relaxed) + 1;
+            resample_progress.set_message(format!(
+                "Resampling klines ({resampled_files}/{total_files} files)"
+            ));
+            Ok(())
+        })
+        .collect::<Result<()>>()?;
+    resample_progress.finish_and_clear();
+
+    let intervals: Vec<String> = to.iter().map(Interval::to_string).collect();
+    info!(
+        "Resampled {total_files} {from} files into {}",
+        intervals.join(", ")
+    );
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use reqwest::Url;
+use std::{fs, path::Path, sync::Arc, thread};
+use tiny_http::{Header, Request, Response, Server};
+
+/// The maximum number of keys per listing page, like S3
+const MAX_KEYS: usize = 1000;
+
+/// Serves a directory like `s3-ap-northeast-1.amazonaws.com/data.binance.vision` (listings) and
+/// `data.binance.vision` (archives) do, so it can be used for both `--listing-url` and `--download-url`
+pub fn serve_mirror(address: &str, data_dir: &Path) -> Result<()> {
+    let server = Arc::new(Server::http(address).map_err(|err| anyhow!("{err}"))?);
+    info!("Serving {} on http://{address}", data_dir.display());
+
+    let number_of_workers = 16;
+    let workers: Vec<_> = (0..number_of_workers)
+        .map(|_| {
+            let server = server.clone();
+            let data_dir = data_dir.to_path_buf();
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    debug!("{} {}", request.method(), request.url());
+                    respond(request, &data_dir).unwrap_or_else(|err| debug!("{err}"));
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    Ok(())
+}
+
+fn respond(request: Request, data_dir: &Path) -> Result<()> {
+    let url = Url::parse(&format!("http://mirror{}", request.url()))?;
+    let key = url.path().trim_start_matches('/');
+    if !is_relative(key) {
+        return Ok(request.respond(Response::empty(400))?);
+    }
+
+    if key.is_empty() {
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+                .unwrap_or_default()
+        };
+        let prefix = query("prefix");
+        if !is_relative(&prefix) {
+            return Ok(request.respond(Response::empty(400))?);
+        }
+        let xml = listing(data_dir, &prefix, &query("marker"));
+        let content_type = Header::from_bytes("Content-Type", "application/xml").unwrap();
+        return Ok(request.respond(Response::from_string(xml).with_header(content_type))?);
+    }
+
+    let path = data_dir.join(key);
+    if !path.is_file() {
+        return Ok(request.respond(Response::empty(404))?);
+    }
+    let data = fs::read(path)?;
+
+    // resume partial downloads, e.g. `Range: bytes=1024-`
+    let range_start = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Range"))
+        .and_then(|header| header.value.as_str().strip_prefix("bytes="))
+        .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+    let response = match range_start {
+        Some(start) if start >= data.len() => Response::from_data(vec![]).with_status_code(416),
+        Some(start) => {
+            let content_range = format!("bytes {start}-{}/{}", data.len() - 1, data.len());
+            Response::from_data(data[start..].to_vec())
+                .with_status_code(206)
+                .with_header(Header::from_bytes("Content-Range", content_range).unwrap())
+        }
+        None => Response::from_data(data),
+    };
+    Ok(request.respond(response)?)
+}
+
+/// Whether a key or prefix stays inside the data directory
+fn is_relative(path: &str) -> bool {
+    !path.starts_with('/') && !path.split('/').any(|segment| segment == "..")
+}
+
+/// A ListBucketResult with delimiter `/`, as parsed into `SymbolResult` and `KlineResult`
+fn listing(data_dir: &Path, prefix: &str, marker: &str) -> String {
+    let (directory, name_prefix) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+
+    let mut keys = vec![];
+    if let Ok(entries) = fs::read_dir(data_dir.join(directory)) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(name_prefix) {
+                continue;
+            }
+            let key = match directory {
+                "" => name,
+                directory => format!("{directory}/{name}"),
+            };
+            let is_prefix = entry.path().is_dir();
+            keys.push(if is_prefix { format!("{key}/") } else { key });
+        }
+    }
+    keys.sort();
+    keys.retain(|key| key.as_str() > marker);
+
+    let is_truncated = keys.len() > MAX_KEYS;
+    keys.truncate(MAX_KEYS);
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#);
+    xml.push_str("<Name>data.binance.vision</Name>");
+    xml.push_str(&format!("<Prefix>{}</Prefix>", escape(prefix)));
+    xml.push_str(&format!("<Marker>{}</Marker>", escape(marker)));
+    if is_truncated {
+        let next_marker = keys.last().unwrap();
+        xml.push_str(&format!("<NextMarker>{}</NextMarker>", escape(next_marker)));
+    }
+    xml.push_str(&format!("<MaxKeys>{MAX_KEYS}</MaxKeys>"));
+    xml.push_str("<Delimiter>/</Delimiter>");
+    xml.push_str(&format!("<IsTruncated>{is_truncated}</IsTruncated>"));
+    for key in keys.iter().filter(|key| !key.ends_with('/')) {
+        xml.push_str(&format!("<Contents><Key>{}</Key></Contents>", escape(key)));
+    }
+    for key in keys.iter().filter(|key| key.ends_with('/')) {
+        xml.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            escape(key)
+        ));
+    }
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,65 @@
+use crate::klines::Kline;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// The most an order can slip, a sell slipping 100% would be filled for nothing
+const MAX_PERCENT: f64 = 99.0;
+
+/// How far from the price market orders are filled, always against the order. Limit orders
+/// fill at their limit price.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case")]
+pub enum SlippageModel {
+    /// Fill at the price
+    #[default]
+    None,
+    /// A fixed number of basis points of the price
+    FixedBps { bps: f64 },
+    /// A fraction of the high to low range of the latest kline
+    BarRange { fraction: f64 },
+    /// `impact` percent times the square root of the order's share of the quote asset volume of
+    /// the latest kline, at most `max` percent (klines without volume always slip `max` percent).
+    /// Only `next-vwap-approx` fills use the kline they fill in, as their price does, too.
+    VolumeParticipation { impact: f64, max: f64 },
+}
+
+impl SlippageModel {
+    /// Fails on negative parameters, which would fill orders at a better price
+    pub fn validate(&self) -> Result<()> {
+        let parameters = match self {
+            SlippageModel::None => vec![],
+            SlippageModel::FixedBps { bps } => vec![("bps", bps)],
+            SlippageModel::BarRange { fraction } => vec![("fraction", fraction)],
+            SlippageModel::VolumeParticipation { impact, max } => {
+                vec![("impact", impact), ("max", max)]
+            }
+        };
+        for (name, value) in parameters {
+            if *value < 0.0 {
+                bail!("The slippage `{name}` must not be negative, got {value}");
+            }
+        }
+        Ok(())
+    }
+
+    /// The slippage in percent of the price for an order of `amount` in the quote asset, at most
+    /// 99%
+    pub fn percent(&self, price: f64, amount: f64, kline: &Kline) -> f64 {
+        let percent = match self {
+            SlippageModel::None => 0.0,
+            SlippageModel::FixedBps { bps } => bps / 100.0,
+            SlippageModel::BarRange { fraction } => {
+                100.0 * fraction * (kline.high - kline.low) / price
+            }
+            SlippageModel::VolumeParticipation { impact, max } => {
+                if kline.quote_asset_volume <= 0.0 {
+                    *max
+                } else {
+                    let participation = amount / kline.quote_asset_volume;
+                    (impact * participation.sqrt()).min(*max)
+                }
+            }
+        };
+        percent.min(MAX_PERCENT)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    klines::Kline,
+    resample::Interval,
+    strategy::{Fill, Order, OrderReason, Side, Strategy},
+    strategy_config::StrategyConfig,
+    trades::AggTradeRecord,
+};
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use log::debug;
+use ta::{
+    indicators::{
+        MovingAverageConvergenceDivergence, OnBalanceVolume, RelativeStrengthIndex,
+        SimpleMovingAverage,
+    },
+    Next,
+};
+
+pub struct SmaRsiStrategy {
+    pub config: StrategyConfig,
+    pub sma_chain: Vec<SimpleMovingAverage>,
+    pub sma_trend: Vec<SimpleMovingAverage>,
+    pub sma_chain_values: Vec<f64>,
+    pub sma_trend_values: Vec<f64>,
+    pub macd: MovingAverageConvergenceDivergence,
+    pub obv: OnBalanceVolume,
+    pub rsi: RelativeStrengthIndex,
+    pub warmup: usize,
+    pub updates: usize,
+    pub entry_price: Option<f64>,
+    pub entry_timestamp: Option<NaiveDateTime>,
+    pub previous_close: Option<f64>,
+}
+
+impl SmaRsiStrategy {
+    pub fn new(config: &StrategyConfig, interval: Interval) -> Result<SmaRsiStrategy> {
+        let indicators = &config.indicators;
+        // the trend periods are minutes, e.g. sma1440 is the daily average on any interval
+        let sma_trend_periods: Vec<usize> = indicators
+            .sma_trend
+            .iter()
+            .map(|minutes| interval.klines(Duration::minutes(*minutes as i64)))
+            .collect();
+        let sma = |periods: &[usize]| -> Result<Vec<SimpleMovingAverage>> {
+            periods
+                .iter()
+                .map(|period| Ok(SimpleMovingAverage::new(*period)?))
+                .collect()
+        };
+
+        // wait until the longest enabled SMA is filled
+        let mut warmup = 0;
+        if config.rules.sma_chain {
+            warmup = warmup.max(indicators.sma_chain.iter().copied().max().unwrap_or(0));
+        }
+        if config.rules.sma_trend {
+            warmup = warmup.max(sma_trend_periods.iter().copied().max().unwrap_or(0));
+        }
+
+        Ok(SmaRsiStrategy {
+            config: config.clone(),
+            sma_chain: sma(&indicators.sma_chain)?,
+            sma_trend: sma(&sma_trend_periods)?,
+            sma_chain_values: vec![0.0; indicators.sma_chain.len()],
+            sma_trend_values: vec![0.0; indicators.sma_trend.len()],
+            macd: MovingAverageConvergenceDivergence::new(
+                indicators.macd.fast,
+                indicators.macd.slow,
+                indicators.macd.signal,
+            )?,
+            obv: OnBalanceVolume::new(),
+            rsi: RelativeStrengthIndex::new(indicators.rsi.period)?,
+            warmup,
+            updates: 0,
+            entry_price: None,
+            entry_timestamp: None,
+            previous_close: None,
+        })
+    }
+
+    /// The take-profit or stop-loss order if the price reached one of them
+    fn price_exit(&self, entry_price: f64, price: f64) -> Option<Order> {
+        let exits = &self.config.exits;
+        let take_profit_price = entry_price * (1.0 + exits.take_profit / 100.0);
+        if price >= take_profit_price {
+            return Some(Order::limit(
+                Side::Sell,
+                take_profit_price,
+                OrderReason::TakeProfit,
+            ));
+        }
+
+        let stop_loss_price = exits
+            .stop_loss
+            .map(|stop_loss| entry_price * (1.0 - stop_loss / 100.0));
+        if stop_loss_price.is_some_and(|stop_loss_price| price <= stop_loss_price) {
+            return Some(Order::market(Side::Sell, OrderReason::StopLoss));
+        }
+
+        None
+    }
+}
+
+/// Returns true if `first > values[0] > values[1] > ...`
+fn is_descending(first: f64, values: &[f64]) -> bool {
+    let mut previous = first;
+    for value in values {
+        if previous <= *value {
+            return false;
+        }
+        previous = *value;
+    }
+    true
+}
+
+impl Strategy for SmaRsiStrategy {
+    fn on_kline(&mut self, kline: &Kline) -> Result<Option<Order>> {
+        for (sma, value) in self.sma_chain.iter_mut().zip(&mut self.sma_chain_values) {
+            *value = sma.next(kline.close);
+        }
+        for (sma, value) in self.sma_trend.iter_mut().zip(&mut self.sma_trend_values) {
+            *value = sma.next(kline.close);
+        }
+        let macd = self.macd.next(kline.close);
+        let obv = self.obv.next(kline);
+        let rsi = self.rsi.next(kline);
+
+        let previous_close = self.previous_close.replace(kline.close);
+        self.updates += 1;
+
+        // buy logic
+        let Some(entry_price) = self.entry_price else {
+            let rules = &self.config.rules;
+            let chain_end = self.sma_chain_values.last().copied().unwrap_or(kline.close);
+            if self.updates >= self.warmup
+                && (!rules.sma_chain || is_descending(kline.close, &self.sma_chain_values))
+                && (!rules.sma_trend || is_descending(chain_end, &self.sma_trend_values))
+                && (!rules.rsi || rsi > self.config.indicators.rsi.threshold)
+                && (!rules.obv_negative || obv < 0.0)
+                && (!rules.macd_histogram_positive || macd.histogram > 0.0)
+            {
+                let trend = if previous_close.is_some_and(|c| c > kline.close) {
+                    "↓"
+                } else {
+                    "↑"
+                };
+                debug!(
+                    "BUY with trend: {trend} {}, macd histogram: {}, obv: {}",
+                    kline.close, macd.histogram, obv
+                );
+                return Ok(Some(Order::market(Side::Buy, OrderReason::Entry)));
+            }
+            return Ok(None);
+        };
+
+        // skip sell logic if there is no buy timestamp
+        let Some(entry_timestamp) = self.entry_timestamp else {
+            return Ok(None);
+        };
+
+        // take profit and stop loss sell logic
+        if let Some(order) = self.price_exit(entry_price, kline.close) {
+            return Ok(Some(order));
+        }
+
+        // stop loss sell because old order
+        let age = kline.open_timestamp() - entry_timestamp;
+        if age.num_days() >= self.config.exits.max_age_days {
+            return Ok(Some(Order::market(Side::Sell, OrderReason::MaxAge)));
+        }
+
+        Ok(None)
+    }
+
+    fn on_trade(&mut self, trade: &AggTradeRecord) -> Result<Option<Order>> {
+        // indicators only update on close, but exits are checked on every trade
+        let Some(entry_price) = self.entry_price else {
+            return Ok(None);
+        };
+        Ok(self.price_exit(entry_price, trade.price))
+    }
+
+    fn on_fill(&mut self, fill: &Fill) {
+        match fill.side {
+            Side::Buy => {
+                self.entry_price = Some(fill.price);
+                self.entry_timestamp = Some(fill.timestamp);
+            }
+            Side::Sell => {
+                self.entry_price = None;
+                self.entry_timestamp = None;
+            }
+        }
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::{
+    klines::Kline, resample::Interval, sma_rsi_strategy::SmaRsiStrategy,
+    strategy_config::StrategyConfig, trades::AggTradeRecord,
+};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderReason {
+    Entry,
+    TakeProfit,
+    StopLoss,
+    MaxAge,
+}
+
+impl fmt::Display for OrderReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderReason::Entry => write!(f, "entry"),
+            OrderReason::TakeProfit => write!(f, "take-profit"),
+            OrderReason::StopLoss => write!(f, "stop-loss"),
+            OrderReason::MaxAge => write!(f, "age"),
+        }
+    }
+}
+
+/// An order emitted by a strategy. Orders without a limit price are filled at the close
+/// or, for orders returned by `on_trade`, at the price of the trade.
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub side: Side,
+    pub limit_price: Option<f64>,
+    pub reason: OrderReason,
+}
+
+impl Order {
+    pub fn market(side: Side, reason: OrderReason) -> Order {
+        Order {
+            side,
+            limit_price: None,
+            reason,
+        }
+    }
+
+    pub fn limit(side: Side, price: f64, reason: OrderReason) -> Order {
+        Order {
+            side,
+            limit_price: Some(price),
+            reason,
+        }
+    }
+}
+
+/// When the orders `on_kline` returns are filled. The close of a kline is only known once it
+/// has ended, so filling at the next kline avoids trading at a price that is already gone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExecutionPolicy {
+    /// Fill at the close of the kline the order was placed on
+    #[default]
+    SameClose,
+    /// Fill at the open of the next kline
+    NextOpen,
+    /// Fill at the average price of the next kline (its quote asset volume divided by its volume,
+    /// or the average of high, low and close without volume)
+    NextVwapApprox,
+}
+
+impl ExecutionPolicy {
+    /// The price a market order placed before the kline fills at
+    pub fn price(&self, kline: &Kline) -> f64 {
+        match self {
+            ExecutionPolicy::SameClose => kline.close,
+            ExecutionPolicy::NextOpen => kline.open,
+            ExecutionPolicy::NextVwapApprox if kline.volume > 0.0 => {
+                kline.quote_asset_volume / kline.volume
+            }
+            ExecutionPolicy::NextVwapApprox => (kline.high + kline.low + kline.close) / 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub side: Side,
+    pub price: f64,
+    pub timestamp: NaiveDateTime,
+}
+
+pub trait Strategy: Send {
+    /// Called for every closed kline, in order. Returns the order to execute, if any.
+    fn on_kline(&mut self, kline: &Kline) -> Result<Option<Order>>;
+
+    /// Called for every trade within a kline before the kline closes, if trades are replayed.
+    /// Returns the order to execute, if any.
+    fn on_trade(&mut self, _trade: &AggTradeRecord) -> Result<Option<Order>> {
+        Ok(None)
+    }
+
+    /// Called after an order has been executed, which is on the next kline for the
+    /// `next-*` execution policies.
+    fn on_fill(&mut self, fill: &Fill);
+
+    /// Called once after the last kline has been processed.
+    fn finalize(&mut self) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyKind {
+    /// SMA chain and RSI entry with take-profit and max age exits
+    SmaRsi,
+}
+
+impl StrategyKind {
+    /// Builds the strategy for klines of the given interval
+    pub fn build(&self, config: &StrategyConfig, interval: Interval) -> Result<Box<dyn Strategy>> {
+        match self {
+            StrategyKind::SmaRsi => Ok(Box::new(SmaRsiStrategy::new(config, interval)?)),
+        }
+    }
+}
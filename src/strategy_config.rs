@@ -0,0 +1,161 @@
+use crate::{market::Market, slippage::SlippageModel, strategy::ExecutionPolicy};
+use serde::{Deserialize, Serialize};
+
+/// Strategy parameters, loaded with `--config` from a TOML or YAML file.
+/// Missing values fall back to the defaults below.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StrategyConfig {
+    /// When orders are filled: `same-close`, `next-open` or `next-vwap-approx`
+    pub execution: ExecutionPolicy,
+    /// Legacy: the fee in percent of maker and taker orders, overrides `fees.maker` and
+    /// `fees.taker`
+    #[serde(skip_serializing)]
+    pub fee: Option<f64>,
+    pub fees: FeeConfig,
+    pub slippage: SlippageModel,
+    pub indicators: IndicatorConfig,
+    pub rules: RuleConfig,
+    pub exits: ExitConfig,
+    pub portfolio: PortfolioConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndicatorConfig {
+    /// SMA periods in klines that have to be strictly descending: close > sma[0] > sma[1] > ...
+    pub sma_chain: Vec<usize>,
+    /// Long running SMA periods in minutes continuing the chain: sma_chain[last] > sma_trend[0] > ...
+    /// They are converted to klines of the tested interval, e.g. 1440 is a 24 period SMA on 1h klines
+    pub sma_trend: Vec<usize>,
+    pub macd: MacdConfig,
+    pub rsi: RsiConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacdConfig {
+    pub fast: usize,
+    pub slow: usize,
+    pub signal: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RsiConfig {
+    pub period: usize,
+    pub threshold: f64,
+}
+
+/// Toggles for the individual buy rule clauses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleConfig {
+    pub sma_chain: bool,
+    pub sma_trend: bool,
+    pub rsi: bool,
+    pub obv_negative: bool,
+    pub macd_histogram_positive: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExitConfig {
+    /// Sell as soon as the price is up by this many percent
+    pub take_profit: f64,
+    /// Sell positions that are older than this many days
+    pub max_age_days: i64,
+    /// Optional: sell as soon as the price is down by this many percent
+    pub stop_loss: Option<f64>,
+}
+
+/// The fee schedule, limit orders pay the maker fee and market orders the taker fee
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeConfig {
+    /// Optional: the market whose fee schedule applies: `spot`, `um` or `cm`, by default the
+    /// market of the tested data directory
+    pub market: Option<Market>,
+    /// The VIP tier (0 to 9)
+    pub vip_tier: usize,
+    /// Optional: maker fee in percent instead of the rate of the VIP tier, including any discount
+    pub maker: Option<f64>,
+    /// Optional: taker fee in percent instead of the rate of the VIP tier, including any discount
+    pub taker: Option<f64>,
+    /// Pay fees in BNB for a discount (not available on COIN-M futures)
+    pub bnb_discount: bool,
+}
+
+/// The account all symbols trade from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortfolioConfig {
+    /// Only symbols quoted in this asset are traded, e.g. `BTCUSDT` for `USDT`
+    pub quote_asset: String,
+    /// Starting capital in the quote asset
+    pub initial_capital: f64,
+    /// Percent of the equity to invest per position, limited by the cash left
+    pub position_size: f64,
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> IndicatorConfig {
+        IndicatorConfig {
+            sma_chain: vec![9, 26, 50, 200, 201],
+            sma_trend: vec![1440, 10080],
+            macd: MacdConfig::default(),
+            rsi: RsiConfig::default(),
+        }
+    }
+}
+
+impl Default for MacdConfig {
+    fn default() -> MacdConfig {
+        MacdConfig {
+            fast: 34,
+            slow: 144,
+            signal: 9,
+        }
+    }
+}
+
+impl Default for RsiConfig {
+    fn default() -> RsiConfig {
+        RsiConfig {
+            period: 14,
+            threshold: 80.0,
+        }
+    }
+}
+
+impl Default for RuleConfig {
+    fn default() -> RuleConfig {
+        RuleConfig {
+            sma_chain: true,
+            sma_trend: false,
+            rsi: true,
+            obv_negative: false,
+            macd_histogram_positive: false,
+        }
+    }
+}
+
+impl Default for ExitConfig {
+    fn default() -> ExitConfig {
+        ExitConfig {
+            take_profit: 5.0,
+            max_age_days: 60,
+            stop_loss: None,
+        }
+    }
+}
+
+impl Default for PortfolioConfig {
+    fn default() -> PortfolioConfig {
+        PortfolioConfig {
+            quote_asset: "USDT".to_string(),
+            initial_capital: 10_000.0,
+            position_size: 10.0,
+        }
+    }
+}
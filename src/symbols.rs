@@ -1,28 +1,29 @@
 use crossbeam::channel::Sender;
 use log::debug;
 use regex::Regex;
-use serde_xml_rs::from_str;
-use std::io::Read;
 
-use crate::types;
+use crate::{
+    download::{self, RetryPolicy},
+    market::Dataset,
+    types::{self, DataSource},
+};
 
 pub fn fetch(
+    source: &DataSource,
+    dataset: Dataset,
     symbol_sender: &Sender<String>,
     symbol_filter: String,
+    retry_policy: &RetryPolicy,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut is_fetching = true;
     let mut next_marker: String = String::new();
 
     while is_fetching {
-        let url = format!("https://s3-ap-northeast-1.amazonaws.com/data.binance.vision?delimiter=/&prefix=data/spot/daily/klines/&marker={next_marker}");
+        let url = source.listing(&dataset.prefix("daily"), &next_marker);
         // TODO: add logger
         //println!("Fetching symbols from {}", url);
         // progress_bar.set_message(format!("Fetching symbols..."));
-        let mut res = reqwest::blocking::get(url)?;
-        let mut body = String::new();
-        res.read_to_string(&mut body)?;
-
-        let doc: types::SymbolResult = from_str(&body).unwrap();
+        let doc: types::SymbolResult = download::fetch_listing(&url, retry_policy)?;
         if let Some(marker) = doc.next_marker {
             next_marker = marker;
         } else {
@@ -1,31 +1,105 @@
 use crate::{
+    fees::FeeModel,
+    kline_cache::KlineSource,
     klines::{self, Kline},
+    metrics,
+    portfolio::{Portfolio, Trade},
+    resample::Interval,
+    strategy::StrategyKind,
+    strategy_config::StrategyConfig,
+    trades::AggTradeRecord,
     trading_signal::TradingSignal,
 };
-use anyhow::{Ok, Result};
+use anyhow::{bail, Ok, Result};
 use chrono::{Duration, NaiveDate};
 
 use colored::Colorize;
-use csv::Reader;
 use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 
-use log::{debug, info};
-use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+use log::{debug, info, warn};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use regex::Regex;
+use serde::Serialize;
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap, HashSet},
     fmt::Write,
-    fs::{self, DirEntry},
-    path::PathBuf,
 };
 
+/// The outcome of a single test run, including everything needed to reproduce it
+#[derive(Debug, Serialize)]
+pub struct TestResult {
+    pub strategy: StrategyKind,
+    pub symbol_filter: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Total return on the initial capital in percent
+    pub performance: f64,
+    /// Cash plus the open positions at the latest close in the quote asset
+    pub final_equity: f64,
+    /// Profit or loss of the closed trades after fees in the quote asset
+    pub realized_pnl: f64,
+    /// Profit or loss of the positions still open at the end in the quote asset
+    pub unrealized_pnl: f64,
+    /// Fees paid on all buys and sells in the quote asset
+    pub fees: f64,
+    /// Cost of the slippage of all buys and sells in the quote asset
+    pub slippage: f64,
+    pub trades: i32,
+    pub open_positions: usize,
+    pub symbols: i32,
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+    pub config: StrategyConfig,
+    /// The closed trades of all symbols, ordered by exit time
+    pub trade_log: Vec<Trade>,
+    /// Total return in percent at the end of each day, including open positions
+    #[serde(skip)]
+    pub daily_performance: Vec<f64>,
+}
+
+/// The averaged outcome of a test-variants run
+#[derive(Debug, Serialize)]
+pub struct VariantsResult {
+    pub strategy: StrategyKind,
+    pub symbol_filter: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub variations: usize,
+    pub average_performance: f64,
+    pub config: StrategyConfig,
+}
+
+/// A kline open or close or a trade of a symbol
+enum Event<'a> {
+    Open(&'a str, &'a Kline),
+    Close(&'a str, &'a Kline),
+    Trade(&'a str, &'a AggTradeRecord),
+}
+
+impl<'a> Event<'a> {
+    /// Orders by time, klines close before the next ones open and these open before their trades
+    fn key(&self, interval: Interval) -> (i64, u8, &'a str) {
+        match self {
+            Event::Close(symbol, kline) => (
+                klines::milliseconds(kline.open_time) + interval.milliseconds(),
+                0,
+                symbol,
+            ),
+            Event::Open(symbol, kline) => (klines::milliseconds(kline.open_time), 1, symbol),
+            Event::Trade(symbol, trade) => (klines::milliseconds(trade.transact_time), 2, symbol),
+        }
+    }
+}
+
 pub fn test(
     symbol_filter: &Regex,
+    strategy: StrategyKind,
+    config: &StrategyConfig,
     start_date: &NaiveDate,
     end_date: &NaiveDate,
-    data_dir: PathBuf,
+    klines: &dyn KlineSource,
     progress: &MultiProgress,
-) -> Result<f64> {
+) -> Result<TestResult> {
     let duration = end_date.signed_duration_since(*start_date).num_days() as u64;
     let progress_bar = progress.add(ProgressBar::new(duration));
     progress_bar.set_message(format!("{start_date} to {end_date}"));
@@ -41,52 +115,94 @@ pub fn test(
     );
 
     let mut signals_by_symbol: HashMap<String, TradingSignal> = HashMap::new();
-    let symbol_path_regex = Regex::new(r"(?P<symbol>\w+)-1m").unwrap();
+    let mut skipped_symbols = HashSet::new();
+    let mut portfolio = Portfolio::new(
+        &config.portfolio,
+        FeeModel::new(&config.fees)?,
+        config.slippage.clone(),
+    );
+    let interval = klines.interval();
+    let mut daily_performance = Vec::with_capacity(duration as usize + 1);
 
     let mut day = start_date.to_owned();
     while day <= *end_date {
         debug!("Processing {:?}", day);
 
-        let dir = data_dir.join(day.format("%Y/%m/%d").to_string());
-        let files: Vec<DirEntry> = fs::read_dir(&dir)
-            .unwrap()
-            .map(|entry| entry.unwrap())
-            .collect();
+        let day_klines = klines.klines(&day)?;
 
         // add trading signals for newly discovered symbols
-        for file in &files {
-            let filepath: PathBuf = file.path();
-            let filepath_str = filepath.to_str().unwrap();
-            let Some(matches) = symbol_path_regex.captures(filepath_str) else {
-                panic!("{}", format!("Can't parse symbol from path {filepath_str}"));
-            };
-
-            let symbol = matches.name("symbol").unwrap().as_str().to_string();
-            if !symbol_filter.is_match(&symbol) {
+        for symbol in day_klines.keys() {
+            if !symbol_filter.is_match(symbol) {
                 continue;
             }
 
-            if !signals_by_symbol.contains_key(&symbol) {
-                debug!("Wild symbol {} appeared", symbol);
-                signals_by_symbol.insert(symbol.clone(), TradingSignal::new(symbol));
+            if let Entry::Vacant(entry) = signals_by_symbol.entry(symbol.clone()) {
+                if !entry.key().ends_with(&config.portfolio.quote_asset) {
+                    if skipped_symbols.insert(entry.key().clone()) {
+                        debug!(
+                            "Skipping {}, which is not quoted in the portfolio asset",
+                            entry.key()
+                        );
+                    }
+                    continue;
+                }
+                debug!("Wild symbol {} appeared", entry.key());
+                let strategy = strategy.build(config, klines.interval())?;
+                let symbol = entry.key().clone();
+                entry.insert(TradingSignal::new(
+                    symbol,
+                    strategy,
+                    config.execution,
+                    interval,
+                ));
             }
         }
 
-        // process trading signals
-        signals_by_symbol
-            .par_iter_mut()
-            .for_each(|(symbol, signal)| {
-                let filepath = dir.join(format!("{symbol}-1m-{}.csv", day.format("%Y-%m-%d")));
-                if filepath.exists() {
-                    let mut reader = Reader::from_path(&filepath).unwrap();
-                    reader.set_headers(klines::csv_headers());
-
-                    for result in reader.deserialize() {
-                        let kline: Kline = result.unwrap();
-                        signal.update(kline).unwrap();
-                    }
+        let symbols: Vec<&String> = day_klines
+            .keys()
+            .filter(|symbol| signals_by_symbol.contains_key(*symbol))
+            .collect();
+        let day_trades = symbols
+            .par_iter()
+            .filter_map(|symbol| {
+                let trades = klines.agg_trades(&day, symbol).transpose()?;
+                Some(trades.map(|trades| (*symbol, trades)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the symbols share the cash of the portfolio, so the klines of all symbols are replayed
+        // in chronological order, including the trades within each kline before it closes
+        let mut events = vec![];
+        for symbol in &symbols {
+            events.extend(
+                day_klines[*symbol]
+                    .iter()
+                    .flat_map(|kline| [Event::Open(symbol, kline), Event::Close(symbol, kline)]),
+            );
+        }
+        for (symbol, trades) in &day_trades {
+            events.extend(trades.iter().map(|trade| Event::Trade(symbol, trade)));
+        }
+        events.sort_by_key(|event| event.key(interval));
+
+        for event in events {
+            match event {
+                Event::Open(symbol, kline) => {
+                    let signal = signals_by_symbol.get_mut(symbol).unwrap();
+                    signal.open(kline, &mut portfolio);
                 }
-            });
+                Event::Close(symbol, kline) => {
+                    let signal = signals_by_symbol.get_mut(symbol).unwrap();
+                    signal.update(kline, &mut portfolio)?;
+                }
+                Event::Trade(symbol, trade) => {
+                    let signal = signals_by_symbol.get_mut(symbol).unwrap();
+                    signal.update_trade(trade, &mut portfolio)?;
+                }
+            }
+        }
+
+        daily_performance.push(portfolio.performance());
 
         day += Duration::days(1);
         progress_bar.inc(1);
@@ -94,30 +210,37 @@ pub fn test(
 
     progress_bar.finish_and_clear();
 
-    let mut total_performance = 0.0;
+    if !skipped_symbols.is_empty() {
+        let quote_asset = &config.portfolio.quote_asset;
+        if signals_by_symbol.is_empty() {
+            bail!(
+                "None of the {} symbols is quoted in {quote_asset}, set `quote_asset` in `[portfolio]`",
+                skipped_symbols.len()
+            );
+        }
+        warn!(
+            "Skipped {} symbols which are not quoted in {quote_asset}",
+            skipped_symbols.len()
+        );
+    }
+
     let mut total_updates = 0;
     let mut total_symbols = 0;
     let mut total_trades = 0;
 
-    // finalize each signal
+    // finalize each signal, open positions are valued at the latest close
     signals_by_symbol.iter_mut().for_each(|(_, signal)| {
-        signal.finalize().unwrap();
+        signal.finalize(&portfolio).unwrap();
     });
 
     // sort by performance
     let mut signals: Vec<TradingSignal> = signals_by_symbol.into_values().collect();
-    signals.sort_by(|a, b| {
-        b.stats
-            .performance
-            .partial_cmp(&a.stats.performance)
-            .unwrap()
-    });
+    signals.sort_by(|a, b| b.stats.performance.total_cmp(&a.stats.performance));
 
     // print stats
     signals.iter().for_each(|signal| {
         total_symbols += 1;
         total_updates += signal.stats.updates;
-        total_performance += signal.stats.performance;
         total_trades += signal.stats.total_sells;
         debug!("{}", signal);
     });
@@ -132,6 +255,7 @@ pub fn test(
         .unwrap()
         .join("'");
 
+    let total_performance = portfolio.performance();
     let performance = if total_performance > 0.0 {
         format!("{}%", total_performance.round()).green()
     } else {
@@ -139,6 +263,40 @@ pub fn test(
     };
 
     debug!("{total_symbols} Symbols discovered and {updates} klines processed.",);
-    info!("Performance from {start_date} to {end_date}: {performance}, trades: {total_trades}");
-    Ok(total_performance)
+    let quote_asset = &config.portfolio.quote_asset;
+    info!(
+        "Performance from {start_date} to {end_date}: {performance} (equity: {:.2} {quote_asset}, realized: {:.2} {quote_asset}, unrealized: {:.2} {quote_asset}, fees: {:.2} {quote_asset}, slippage: {:.2} {quote_asset}), trades: {total_trades}, open positions: {}",
+        portfolio.equity(),
+        portfolio.realized_pnl,
+        portfolio.unrealized_pnl(),
+        portfolio.total_fees,
+        portfolio.total_slippage,
+        portfolio.positions.len()
+    );
+
+    let mut trade_log: Vec<Trade> = signals
+        .into_iter()
+        .flat_map(|signal| signal.trades)
+        .collect();
+    trade_log.sort_by_key(|trade| trade.exit_time);
+    Ok(TestResult {
+        strategy,
+        symbol_filter: symbol_filter.to_string(),
+        start_date: *start_date,
+        end_date: *end_date,
+        performance: total_performance,
+        final_equity: portfolio.equity(),
+        realized_pnl: portfolio.realized_pnl,
+        unrealized_pnl: portfolio.unrealized_pnl(),
+        fees: portfolio.total_fees,
+        slippage: portfolio.total_slippage,
+        trades: total_trades,
+        open_positions: portfolio.positions.len(),
+        symbols: total_symbols,
+        sharpe_ratio: metrics::sharpe_ratio(&daily_performance),
+        max_drawdown: metrics::max_drawdown(&daily_performance),
+        config: config.clone(),
+        trade_log,
+        daily_performance,
+    })
 }
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use csv::{ReaderBuilder, StringRecord};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use std::path::Path;
+
+/// A trade of the `aggTrades` dataset, aggregated by price and taker side
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct AggTradeRecord {
+    pub agg_trade_id: i64,
+    pub price: f64,
+    pub quantity: f64,
+    pub first_trade_id: i64,
+    pub last_trade_id: i64,
+    pub transact_time: i64,
+    #[serde(deserialize_with = "deserialize_bool")]
+    pub is_buyer_maker: bool,
+    /// Only published for spot markets
+    #[serde(default, deserialize_with = "deserialize_optional_bool")]
+    pub is_best_match: Option<bool>,
+}
+
+/// The columns of the `trades` dataset
+pub fn trade_headers() -> StringRecord {
+    // https://github.com/binance/binance-public-data/
+    StringRecord::from(vec![
+        "id",
+        "price",
+        "qty",
+        "quote_qty",
+        "time",
+        "is_buyer_maker",
+        "is_best_match",
+    ])
+}
+
+pub fn agg_trade_headers() -> StringRecord {
+    StringRecord::from(vec![
+        "agg_trade_id",
+        "price",
+        "quantity",
+        "first_trade_id",
+        "last_trade_id",
+        "transact_time",
+        "is_buyer_maker",
+        "is_best_match",
+    ])
+}
+
+/// Deserializes a record, trailing columns that are missing (e.g. `is_best_match` of futures) are ignored
+pub fn deserialize<T: DeserializeOwned>(
+    record: &StringRecord,
+    headers: &StringRecord,
+) -> Result<T> {
+    let headers: StringRecord = headers.iter().take(record.len()).collect();
+    Ok(record.deserialize(Some(&headers))?)
+}
+
+/// Reads a daily aggTrades CSV file
+pub fn read_agg_trades(path: &Path) -> Result<Vec<AggTradeRecord>> {
+    let mut reader = ReaderBuilder::new().has_headers(false).from_path(path)?;
+    let headers = agg_trade_headers();
+    reader
+        .records()
+        .map(|record| deserialize(&record?, &headers))
+        .collect()
+}
+
+/// Spot files contain `True`/`False`, futures files `true`/`false`
+fn deserialize_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match value.to_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(serde::de::Error::custom(format!("Invalid bool {value}"))),
+    }
+}
+
+fn deserialize_optional_bool<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<bool>, D::Error> {
+    deserialize_bool(deserializer).map(Some)
+}
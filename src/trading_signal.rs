@@ -1,21 +1,28 @@
 use std::fmt;
 
-use crate::klines::Kline;
+use crate::{
+    fees::Liquidity,
+    klines::{self, Kline},
+    metrics,
+    portfolio::{Portfolio, Trade},
+    resample::Interval,
+    strategy::{ExecutionPolicy, Fill, Order, OrderReason, Side, Strategy},
+    trades::AggTradeRecord,
+};
 use anyhow::Ok;
 use chrono::NaiveDateTime;
 use colored::Colorize;
 use log::debug;
-use ta::{
-    indicators::{
-        MovingAverageConvergenceDivergence, OnBalanceVolume, RelativeStrengthIndex,
-        SimpleMovingAverage,
-    },
-    Next,
-};
 
 pub struct TradingStatistics {
+    /// Compounded return of the closed trades in percent
     pub performance: f64,
+    /// Fees of the closed trades in the quote asset
     pub total_fee: f64,
+    /// Profit or loss of the closed trades in the quote asset
+    pub realized_pnl: f64,
+    /// Profit or loss of the open position at the latest close in the quote asset
+    pub unrealized_pnl: f64,
     pub total_buys: i32,
     pub total_sells: i32,
     pub total_profitable_sells: i32,
@@ -30,20 +37,18 @@ pub struct SymbolInfo {
 pub struct TradingSignal {
     pub symbol: SymbolInfo,
     pub stats: TradingStatistics,
-    pub sma9: SimpleMovingAverage,
-    pub sma26: SimpleMovingAverage,
-    pub sma50: SimpleMovingAverage,
-    pub sma200: SimpleMovingAverage,
-    pub sma201: SimpleMovingAverage,
-    pub sma1440: SimpleMovingAverage,
-    pub sma10080: SimpleMovingAverage,
-    pub macd: MovingAverageConvergenceDivergence,
-    pub obv: OnBalanceVolume,
-    pub rsi: RelativeStrengthIndex,
+    pub strategy: Box<dyn Strategy>,
+    pub execution: ExecutionPolicy,
+    pub interval: Interval,
+    /// The order of the previous kline, which fills within the current one for the `next-*`
+    /// execution policies
+    pub pending_order: Option<Order>,
+    /// The closed trades, in order
+    pub trades: Vec<Trade>,
     pub current_buy_price: Option<f64>,
     pub latest_buy_timestamp: Option<NaiveDateTime>,
     pub latest_sell_timestamp: Option<NaiveDateTime>,
-    pub latest_close: Option<f64>,
+    pub latest_kline: Option<Kline>,
 }
 
 impl fmt::Display for TradingSignal {
@@ -53,194 +58,304 @@ impl fmt::Display for TradingSignal {
         let sells = self.stats.total_sells;
         let profitable_sells = self.stats.total_profitable_sells;
         let performance = if self.stats.performance > 0.0 {
-            self.stats.performance.to_string().green()
+            format!("{:.2}%", self.stats.performance).green()
         } else if self.stats.performance < 0.0 {
-            self.stats.performance.to_string().red()
+            format!("{:.2}%", self.stats.performance).red()
         } else {
             "n/A".white()
         };
-        write!(f, "{symbol}'s performance: {performance}, fees: {fees}, profitable trades: {profitable_sells}/{sells}")
+        let realized = self.stats.realized_pnl;
+        let unrealized = self.stats.unrealized_pnl;
+        write!(f, "{symbol}'s performance: {performance}, realized: {realized:.2}, unrealized: {unrealized:.2}, fees: {fees:.2}, profitable trades: {profitable_sells}/{sells}")
     }
 }
 
 impl TradingSignal {
-    pub fn new(symbol: String) -> TradingSignal {
+    pub fn new(
+        symbol: String,
+        strategy: Box<dyn Strategy>,
+        execution: ExecutionPolicy,
+        interval: Interval,
+    ) -> TradingSignal {
         TradingSignal {
             symbol: SymbolInfo { name: symbol },
             stats: TradingStatistics {
                 performance: 0.0,
                 updates: 0,
                 total_fee: 0.0,
+                realized_pnl: 0.0,
+                unrealized_pnl: 0.0,
                 total_buys: 0,
                 total_sells: 0,
                 total_profitable_sells: 0,
                 total_stoploss_sells: 0,
             },
-            sma9: SimpleMovingAverage::new(9).unwrap(),
-            sma26: SimpleMovingAverage::new(26).unwrap(),
-            sma50: SimpleMovingAverage::new(50).unwrap(),
-            sma200: SimpleMovingAverage::new(200).unwrap(),
-            sma201: SimpleMovingAverage::new(201).unwrap(),
-            sma1440: SimpleMovingAverage::new(1440).unwrap(),
-            sma10080: SimpleMovingAverage::new(10080).unwrap(),
-            macd: MovingAverageConvergenceDivergence::new(34, 144, 9).unwrap(),
-            obv: OnBalanceVolume::new(),
-            rsi: RelativeStrengthIndex::new(14).unwrap(),
+            strategy,
+            execution,
+            interval,
+            pending_order: None,
+            trades: vec![],
             current_buy_price: None,
             latest_buy_timestamp: None,
             latest_sell_timestamp: None,
-            latest_close: None,
+            latest_kline: None,
+        }
+    }
+
+    /// Fills the order placed on the previous kline as the kline opens, before its trades are
+    /// replayed. Orders are cancelled by gaps in the klines, e.g. missing days.
+    pub fn open(&mut self, kline: &Kline, portfolio: &mut Portfolio) {
+        let Some(order) = self.pending_order.take() else {
+            return;
+        };
+        let Some(previous) = self.latest_kline.clone() else {
+            return;
+        };
+        if klines::milliseconds(kline.open_time)
+            != klines::milliseconds(previous.open_time) + self.interval.milliseconds()
+        {
+            debug!(
+                "Dropping the order of {} after a gap before {}",
+                self.symbol.name,
+                kline.open_timestamp()
+            );
+            return;
         }
+        self.fill_pending(order, kline, &previous, portfolio);
     }
 
-    pub fn update(&mut self, kline: Kline) -> Result<(), anyhow::Error> {
-        let sma9 = self.sma9.next(kline.close);
-        let sma26 = self.sma26.next(kline.close);
-        let sma50 = self.sma50.next(kline.close);
-        let sma200 = self.sma200.next(kline.close);
-        let sma201 = self.sma201.next(kline.close);
-        let sma1440 = self.sma1440.next(kline.close);
-        let sma10080 = self.sma10080.next(kline.close);
-        let macd = self.macd.next(kline.close);
-        let obv = self.obv.next(&kline);
-        let rsi = self.rsi.next(&kline);
-
-        let timestamp = NaiveDateTime::from_timestamp_opt(kline.open_time / 1000, 0)
-            .expect("Invalid timestamp");
-        let trading_fee = 0.1;
-
-        self.latest_close = Some(kline.close);
+    pub fn update(
+        &mut self,
+        kline: &Kline,
+        portfolio: &mut Portfolio,
+    ) -> Result<(), anyhow::Error> {
+        self.latest_kline = Some(kline.clone());
         self.stats.updates += 1;
+        portfolio.mark(&self.symbol.name, kline.close);
 
-        // buy logic
-        if self.current_buy_price.is_none()
-            && self.stats.updates >= 201
-            && kline.close > sma9
-            && sma9 > sma26
-            && sma26 > sma50
-            && sma50 > sma200
-            && sma200 > sma201
-        // && sma200 > sma1440
-        // && sma1440 > sma10080
-        && rsi > 80.0
-        // && obv < 0.0
-        // && macd.histogram > 0.0
-        {
-            // info!("Buy {} for {}", self.symbol.name.yellow(), kline.close);
-            self.current_buy_price = Some(kline.close);
-            self.latest_buy_timestamp = Some(timestamp);
-            self.stats.performance -= trading_fee;
-            self.stats.total_buys += 1;
-            if self.latest_close.is_some_and(|c| c > kline.close) {
-                debug!(
-                    "BUY with trend: ↓ {}, macd histogram: {}, obv: {}",
-                    kline.close, macd.histogram, obv
-                );
-            } else {
-                debug!(
-                    "BUY with trend: ↑ {}, macd histogram: {}, obv: {}",
-                    kline.close, macd.histogram, obv
-                );
-            }
+        let Some(order) = self.strategy.on_kline(kline)? else {
             return Ok(());
+        };
+        match self.execution {
+            ExecutionPolicy::SameClose => {
+                let (price, liquidity) = market_fill(&order, kline.close);
+                self.execute(
+                    order,
+                    price,
+                    liquidity,
+                    kline.open_timestamp(),
+                    kline,
+                    portfolio,
+                )
+            }
+            ExecutionPolicy::NextOpen | ExecutionPolicy::NextVwapApprox => {
+                self.pending_order = Some(order)
+            }
         }
+        Ok(())
+    }
 
-        // skip sell logic if there is no buy_price
-        let Some(current_buy_price) = self.current_buy_price else {
-            return Ok(());
+    /// Fills the order placed on the previous kline at the price of the execution policy.
+    /// Limit orders fill at their limit price (or a better open) if the kline reaches it and are
+    /// cancelled otherwise. Fills at the open slip by the previous kline, which is known by then,
+    /// fills at the approximated VWAP by the kline itself.
+    fn fill_pending(
+        &mut self,
+        order: Order,
+        kline: &Kline,
+        previous: &Kline,
+        portfolio: &mut Portfolio,
+    ) {
+        let limit_price = match (order.side, order.limit_price) {
+            (_, None) => None,
+            (Side::Sell, Some(limit_price)) if kline.high >= limit_price => {
+                Some(limit_price.max(kline.open))
+            }
+            (Side::Buy, Some(limit_price)) if kline.low <= limit_price => {
+                Some(limit_price.min(kline.open))
+            }
+            (_, Some(_)) => return,
+        };
+        let order = Order {
+            limit_price,
+            ..order
+        };
+        let (price, liquidity) = market_fill(&order, self.execution.price(kline));
+        let slippage_kline = match self.execution {
+            ExecutionPolicy::NextVwapApprox => kline,
+            _ => previous,
         };
+        self.execute(
+            order,
+            price,
+            liquidity,
+            kline.open_timestamp(),
+            slippage_kline,
+            portfolio,
+        );
+    }
 
-        // skip sell logic if there is no buy timestamp
-        let Some(latest_buy_timestamp) = self.latest_buy_timestamp else {
+    /// Replays a trade within the current kline, e.g. for intrabar exits
+    pub fn update_trade(
+        &mut self,
+        trade: &AggTradeRecord,
+        portfolio: &mut Portfolio,
+    ) -> Result<(), anyhow::Error> {
+        portfolio.mark(&self.symbol.name, trade.price);
+        let Some(order) = self.strategy.on_trade(trade)? else {
             return Ok(());
         };
+        // the latest closed kline determines the slippage, orders are only placed after the first
+        let Some(kline) = self.latest_kline.clone() else {
+            return Ok(());
+        };
+        // limit orders rest in the order book until a trade reaches them, even if it gapped
+        // through the limit price
+        let (price, liquidity) = match order.limit_price {
+            Some(limit_price) => (limit_price, Liquidity::Maker),
+            None => (trade.price, Liquidity::Taker),
+        };
+        self.execute(
+            order,
+            price,
+            liquidity,
+            klines::timestamp(trade.transact_time),
+            &kline,
+            portfolio,
+        );
+        Ok(())
+    }
+
+    /// Executes the order at the price, the kline determines the slippage of taker orders
+    fn execute(
+        &mut self,
+        order: Order,
+        price: f64,
+        liquidity: Liquidity,
+        timestamp: NaiveDateTime,
+        slippage_kline: &Kline,
+        portfolio: &mut Portfolio,
+    ) {
+        let fill = match order.side {
+            Side::Buy if self.current_buy_price.is_none() => {
+                // skip buys if all cash is invested
+                let Some(price) = portfolio.buy(
+                    &self.symbol.name,
+                    price,
+                    timestamp,
+                    liquidity,
+                    slippage_kline,
+                ) else {
+                    return;
+                };
+                self.buy(price, timestamp)
+            }
+            Side::Sell if self.current_buy_price.is_some() => {
+                let Some(trade) = portfolio.sell(
+                    &self.symbol.name,
+                    price,
+                    timestamp,
+                    liquidity,
+                    slippage_kline,
+                ) else {
+                    return;
+                };
+                self.sell(&order, trade)
+            }
+            // ignore orders that do not match the current position
+            _ => return,
+        };
+        self.strategy.on_fill(&fill);
+    }
 
-        let price_change = 100.0 / current_buy_price * kline.close - 100.0;
+    fn buy(&mut self, price: f64, timestamp: NaiveDateTime) -> Fill {
+        // info!("Buy {} for {}", self.symbol.name.yellow(), price);
+        self.current_buy_price = Some(price);
+        self.latest_buy_timestamp = Some(timestamp);
+        self.stats.total_buys += 1;
 
-        // profitable sell logic
-        if price_change >= 5.0 {
-            let label = "SELL".green();
-            let symbol = self.symbol.name.yellow();
-            let close = kline.close;
-            debug!("{label} {symbol} for {close} (bought at {current_buy_price})");
-            self.stats.performance += 5.0;
-            self.stats.performance -= trading_fee;
-            self.stats.total_fee += trading_fee;
-            self.stats.total_sells += 1;
-            self.stats.total_profitable_sells += 1;
-            self.current_buy_price = None;
-            self.latest_sell_timestamp = Some(timestamp);
-            return Ok(());
+        Fill {
+            side: Side::Buy,
+            price,
+            timestamp,
         }
+    }
 
-        // stop loss sell because old order
-        let age = timestamp - latest_buy_timestamp;
-        if age.num_days() >= 60 {
+    fn sell(&mut self, order: &Order, trade: Trade) -> Fill {
+        let symbol = self.symbol.name.yellow();
+        let (entry_price, exit_price) = (trade.entry_price, trade.exit_price);
+        let trade_return = trade.return_percent();
+        let pnl = trade.pnl();
+        let fees = trade.fees();
+
+        if order.reason == OrderReason::TakeProfit {
+            let label = "SELL".green();
+            debug!("{label} {symbol} for {exit_price} (bought at {entry_price}, {trade_return:.2}%, {pnl:.2}, fees: {fees:.2})");
+        } else {
             // TODO: maybe it's not a stop loss sell... CHECK
-            let label = "STOP-LOSS SELL (age)".red();
-            let symbol = self.symbol.name.yellow();
-            let close = kline.close;
-            debug!(
-                "{label} {symbol} for {close} (bought at {current_buy_price}, {}%)",
-                price_change
-            );
-            self.stats.performance += price_change;
-            self.stats.performance -= trading_fee;
-            self.stats.total_fee += trading_fee;
-            self.stats.total_sells += 1;
-            if price_change > 0.0 {
-                self.stats.total_profitable_sells += 1;
-            } else {
-                self.stats.total_stoploss_sells += 1;
-            }
-            self.current_buy_price = None;
-            self.latest_sell_timestamp = Some(timestamp);
-            return Ok(());
+            let label = format!("STOP-LOSS SELL ({})", order.reason).red();
+            debug!("{label} {symbol} for {exit_price} (bought at {entry_price}, {trade_return:.2}%, {pnl:.2}, fees: {fees:.2})");
         }
 
-        Ok(())
+        self.stats.performance = metrics::compound(self.stats.performance, trade_return);
+        self.stats.realized_pnl += pnl;
+        self.stats.total_fee += fees;
+        self.stats.total_sells += 1;
+        if pnl > 0.0 {
+            self.stats.total_profitable_sells += 1;
+        } else {
+            self.stats.total_stoploss_sells += 1;
+        }
+        self.current_buy_price = None;
+        self.latest_sell_timestamp = Some(trade.exit_time);
+
+        let fill = Fill {
+            side: Side::Sell,
+            price: exit_price,
+            timestamp: trade.exit_time,
+        };
+        self.trades.push(trade);
+        fill
     }
 
-    pub fn finalize(&mut self) -> Result<(), anyhow::Error> {
-        let Some(latest_close) = self.latest_close else {
+    /// Finishes the strategy, an open position stays open and is valued at the latest close
+    pub fn finalize(&mut self, portfolio: &Portfolio) -> Result<(), anyhow::Error> {
+        self.strategy.finalize()?;
+
+        if self.latest_kline.is_none() {
             panic!(
-                "{} has been finalized but contains no latest_close value",
+                "{} has been finalized but contains no latest_kline value",
                 self.symbol.name
             );
-        };
-
-        // skip if there is no open order
-        let Some(current_buy_price) = self.current_buy_price else {
-            return Ok(());
-        };
-
-        let price_change = 100.0 / current_buy_price * latest_close - 100.0;
-        let symbol = self.symbol.name.clone();
-        let trading_fee = 0.1;
-
-        // TODO
-        // self.latest_sell_timestamp = Some(timestamp);
-        self.current_buy_price = None;
-        self.stats.performance += price_change;
-        self.stats.performance -= trading_fee;
-        self.stats.total_fee += trading_fee;
-        self.stats.total_sells += 1;
+        }
 
-        if price_change > 0.0 {
-            let label = "SELL (finalize)".green();
-            self.stats.total_profitable_sells += 1;
+        if let Some(position) = portfolio.positions.get(&self.symbol.name) {
+            self.stats.unrealized_pnl = position.unrealized_pnl();
             debug!(
-                "{label} {symbol} for {latest_close} (bought at {current_buy_price}, {}%)",
-                price_change
-            );
-        } else {
-            let label = "STOP-LOSS SELL (finalize)".red();
-            self.stats.total_stoploss_sells += 1;
-            debug!(
-                "{label} {symbol} for {latest_close} (bought at {current_buy_price}, {}%)",
-                price_change
+                "OPEN {} since {} (bought at {}, latest close {}, {:.2})",
+                self.symbol.name.yellow(),
+                position.entry_time,
+                position.entry_price,
+                position.price,
+                self.stats.unrealized_pnl
             );
         }
         Ok(())
     }
 }
+
+/// The price and liquidity of an order placed at the market price, at the close or the open of a
+/// kline. Limit orders the market price already crossed fill at the better market price and take
+/// liquidity.
+fn market_fill(order: &Order, market_price: f64) -> (f64, Liquidity) {
+    match (order.side, order.limit_price) {
+        (_, None) => (market_price, Liquidity::Taker),
+        (Side::Sell, Some(limit_price)) if market_price >= limit_price => {
+            (market_price, Liquidity::Taker)
+        }
+        (Side::Buy, Some(limit_price)) if market_price <= limit_price => {
+            (market_price, Liquidity::Taker)
+        }
+        (_, Some(limit_price)) => (limit_price, Liquidity::Maker),
+    }
+}
@@ -1,7 +1,11 @@
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
+use crate::{layout::Layout, market::Dataset};
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct SymbolResult {
@@ -29,7 +33,69 @@ pub struct Contents {
     pub key: String,
 }
 
-pub struct KlineArchive {
-    pub target_directory: PathBuf,
+/// Where the archives are listed (S3 ListBucket API) and downloaded from
+#[derive(Debug, Clone)]
+pub struct DataSource {
+    pub listing_url: String,
+    pub download_url: String,
+}
+
+impl DataSource {
+    /// The listing of all keys and common prefixes directly below the prefix, starting after the marker
+    pub fn listing(&self, prefix: &str, marker: &str) -> String {
+        format!(
+            "{}?delimiter=/&prefix={prefix}&marker={marker}",
+            self.listing_url.trim_end_matches('/')
+        )
+    }
+
+    pub fn archive(&self, key: &str) -> String {
+        format!("{}/{key}", self.download_url.trim_end_matches('/'))
+    }
+}
+
+/// A downloaded daily or monthly archive
+pub struct Archive {
+    pub url: String,
     pub temp_file_path: PathBuf,
+    pub data_dir: PathBuf,
+    pub layout: Layout,
+    pub dataset: Dataset,
+    pub symbol: String,
+    /// The interval of klines or the dataset name of trades, e.g. `1m` or `aggTrades`
+    pub interval: String,
+    /// The day of a daily archive, the rows of monthly archives are split by their time
+    pub date: Option<NaiveDate>,
+    pub format: StorageFormat,
+    /// Whether stored days are only replaced by days with more rows, to repair incomplete files
+    pub repair: bool,
+}
+
+/// Which Binance archives to download
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Granularity {
+    /// One archive per day
+    Daily,
+    /// One archive per month, split into days
+    Monthly,
+    /// Monthly archives for complete months, daily archives for the rest
+    Auto,
+}
+
+/// The file format(s) downloaded klines are stored in
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum StorageFormat {
+    Csv,
+    Parquet,
+    Both,
+}
+
+impl StorageFormat {
+    pub fn csv(&self) -> bool {
+        matches!(self, StorageFormat::Csv | StorageFormat::Both)
+    }
+
+    pub fn parquet(&self) -> bool {
+        matches!(self, StorageFormat::Parquet | StorageFormat::Both)
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{
+    kline_cache::KlineSource,
+    metrics,
+    optimize_command::{self, Objective, ParameterRange},
+    strategy::StrategyKind,
+    strategy_config::StrategyConfig,
+    test_command,
+};
+use anyhow::{bail, Ok, Result};
+use chrono::{Duration, NaiveDate};
+use colored::Colorize;
+use csv::Writer;
+use indicatif::MultiProgress;
+use log::info;
+use regex::Regex;
+use std::path::PathBuf;
+
+/// A train (in-sample) range followed by an unseen test (out-of-sample) range
+pub struct Window {
+    pub train_start: NaiveDate,
+    pub train_end: NaiveDate,
+    pub test_start: NaiveDate,
+    pub test_end: NaiveDate,
+}
+
+pub struct WindowResult {
+    pub window: Window,
+    pub values: Vec<f64>,
+    pub in_sample_score: f64,
+    pub performance: f64,
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+    pub trades: i32,
+}
+
+/// Rolling windows over the date range, moving forward by the length of the test range
+pub fn windows(
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+    train_days: i64,
+    test_days: i64,
+) -> Vec<Window> {
+    let mut windows = vec![];
+    let mut train_start = *start_date;
+    loop {
+        let train_end = train_start + Duration::days(train_days - 1);
+        let test_start = train_end + Duration::days(1);
+        if test_start > *end_date {
+            break;
+        }
+        let test_end = (test_start + Duration::days(test_days - 1)).min(*end_date);
+        windows.push(Window {
+            train_start,
+            train_end,
+            test_start,
+            test_end,
+        });
+        train_start += Duration::days(test_days);
+    }
+    windows
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn walk_forward(
+    symbol_filter: &Regex,
+    strategy: StrategyKind,
+    base_config: &StrategyConfig,
+    ranges: &[ParameterRange],
+    objective: Objective,
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+    train_days: i64,
+    test_days: i64,
+    klines: &dyn KlineSource,
+    progress: &MultiProgress,
+) -> Result<Vec<WindowResult>> {
+    if train_days < 1 || test_days < 1 {
+        bail!("Train and test ranges need to be at least one day long");
+    }
+
+    let windows = windows(start_date, end_date, train_days, test_days);
+    if windows.is_empty() {
+        bail!("{start_date} to {end_date} is too short for {train_days} train days");
+    }
+
+    let mut results = vec![];
+    let mut total_performance = 0.0;
+    let mut daily_performance = vec![];
+
+    for window in windows {
+        info!(
+            "Optimizing on {} to {}, testing on {} to {}",
+            window.train_start, window.train_end, window.test_start, window.test_end
+        );
+        let optimizations = optimize_command::optimize(
+            symbol_filter,
+            strategy,
+            base_config,
+            ranges,
+            objective,
+            &window.train_start,
+            &window.train_end,
+            klines,
+            progress,
+        )?;
+        let Some(best) = optimizations.into_iter().next() else {
+            bail!("No parameter combinations to test");
+        };
+
+        let out_of_sample = test_command::test(
+            symbol_filter,
+            strategy,
+            &best.result.config,
+            &window.test_start,
+            &window.test_end,
+            klines,
+            progress,
+        )?;
+
+        // stitch the out-of-sample curves together, reinvesting the equity of each window
+        daily_performance.extend(
+            out_of_sample
+                .daily_performance
+                .iter()
+                .map(|performance| metrics::compound(total_performance, *performance)),
+        );
+        total_performance = metrics::compound(total_performance, out_of_sample.performance);
+
+        results.push(WindowResult {
+            window,
+            in_sample_score: objective.score(&best.result),
+            values: best.values,
+            performance: out_of_sample.performance,
+            sharpe_ratio: out_of_sample.sharpe_ratio,
+            max_drawdown: out_of_sample.max_drawdown,
+            trades: out_of_sample.trades,
+        });
+    }
+
+    let performance = if total_performance > 0.0 {
+        format!("{}%", total_performance.round()).green()
+    } else {
+        format!("{}%", total_performance.round()).red()
+    };
+    info!(
+        "Out-of-sample performance from {start_date} to {end_date} with {} windows: {performance}, sharpe: {:.2}, max drawdown: {:.1}%",
+        results.len(),
+        metrics::sharpe_ratio(&daily_performance),
+        metrics::max_drawdown(&daily_performance),
+    );
+
+    Ok(results)
+}
+
+pub fn write_csv(
+    path: &PathBuf,
+    ranges: &[ParameterRange],
+    results: &[WindowResult],
+) -> Result<()> {
+    let mut writer = Writer::from_path(path)?;
+    let mut headers: Vec<String> = ["train_start", "train_end", "test_start", "test_end"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect();
+    headers.extend(ranges.iter().map(|range| range.parameter.name()));
+    headers.extend(
+        [
+            "in_sample_score",
+            "performance",
+            "sharpe_ratio",
+            "max_drawdown",
+            "trades",
+        ]
+        .iter()
+        .map(|header| header.to_string()),
+    );
+    writer.write_record(&headers)?;
+
+    for result in results {
+        let window = &result.window;
+        let mut record: Vec<String> = [
+            window.train_start,
+            window.train_end,
+            window.test_start,
+            window.test_end,
+        ]
+        .iter()
+        .map(NaiveDate::to_string)
+        .collect();
+        record.extend(result.values.iter().map(f64::to_string));
+        record.push(result.in_sample_score.to_string());
+        record.push(result.performance.to_string());
+        record.push(result.sharpe_ratio.to_string());
+        record.push(result.max_drawdown.to_string());
+        record.push(result.trades.to_string());
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}